@@ -0,0 +1,105 @@
+use crate::{Point, Ray};
+
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Aabb {
+    #[must_use]
+    pub const fn new(min: Point, max: Point) -> Self {
+        Self { min, max }
+    }
+
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self::new(Point::all(f64::INFINITY), Point::all(f64::NEG_INFINITY))
+    }
+
+    #[must_use]
+    pub fn from_points(a: Point, b: Point) -> Self {
+        Self::new(a.min(b), a.max(b))
+    }
+
+    #[must_use]
+    pub fn surrounding(&self, other: &Aabb) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    #[must_use]
+    pub fn including(&self, point: Point) -> Self {
+        Self::new(self.min.min(point), self.max.max(point))
+    }
+
+    /// Grows zero-thickness axes, as produced by axis-aligned triangles, to `delta`.
+    #[must_use]
+    pub fn padded(&self, delta: f64) -> Self {
+        let pad = |min: f64, max: f64| {
+            if max - min < delta {
+                (min - delta / 2., max + delta / 2.)
+            } else {
+                (min, max)
+            }
+        };
+        let (x0, x1) = pad(self.min.x, self.max.x);
+        let (y0, y1) = pad(self.min.y, self.max.y);
+        let (z0, z1) = pad(self.min.z, self.max.z);
+        Self::new(Point::new(x0, y0, z0), Point::new(x1, y1, z1))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn centroid(&self) -> Point {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn extent(&self) -> Point {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.;
+        }
+        let e = self.extent();
+        2. * (e.x * e.y + e.y * e.z + e.z * e.x)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let e = self.extent();
+        if e.x > e.y && e.x > e.z {
+            0
+        } else if e.y > e.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1. / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // written so that NaNs from 0 * inf fall through without clipping
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
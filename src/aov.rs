@@ -0,0 +1,414 @@
+use crate::{srgb_to_linear, Collision, Color, ExrImage, Framebuffer, Point, Ray, Scene, Vec3};
+use std::str::FromStr;
+
+/// Arbitrary output variables: facts about what each pixel's camera rays hit first, written
+/// next to the beauty image for compositing and denoising.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aov {
+    /// Distance from the camera, infinite where nothing was hit.
+    Depth,
+    /// World-space shading normal.
+    Normal,
+    /// The material's reflectance color, see [`crate::Material::albedo`].
+    Albedo,
+    /// 1 plus the hit object's position in the scene's object list, 0 for the background.
+    ObjectId,
+    /// The hit material's number, see [`Scene::material_id`]; 0 for the background.
+    MaterialId,
+    /// World-space hit point.
+    Position,
+    /// How many samples the pixel took; shown as a heatmap, which with adaptive sampling
+    /// tells where the render's time went.
+    SampleCount,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 7] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Position,
+        Aov::SampleCount,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Position => "position",
+            Aov::SampleCount => "sample_count",
+        }
+    }
+
+    /// The conventional OpenEXR channel names, one per component.
+    pub fn exr_channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["N.X", "N.Y", "N.Z"],
+            Aov::Albedo => &["albedo.R", "albedo.G", "albedo.B"],
+            Aov::ObjectId => &["objectId"],
+            Aov::MaterialId => &["materialId"],
+            Aov::Position => &["P.X", "P.Y", "P.Z"],
+            Aov::SampleCount => &["sampleCount"],
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Aov::ALL
+            .into_iter()
+            .find(|aov| aov.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Aov::ALL.iter().map(|aov| aov.name()).collect();
+                format!("unknown AOV '{s}', expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// First hits gathered over one pixel's samples.
+#[derive(Debug, Clone, Default)]
+pub struct AovPixel {
+    samples: u32,
+    hits: u32,
+    depth: f64,
+    normal: Vec3,
+    position: Point,
+    albedo: Color,
+    object_id: Option<usize>,
+    material_id: Option<usize>,
+}
+
+impl AovPixel {
+    /// Records one camera sample; the first one to hit anything also decides the IDs.
+    pub fn add(&mut self, ray: &Ray, collision: Option<&Collision>, scene: &Scene) {
+        self.samples += 1;
+        let Some(collision) = collision else {
+            return;
+        };
+        self.hits += 1;
+        self.depth += collision.dist * ray.direction.len();
+        self.normal = self.normal + collision.normal;
+        self.position = self.position + collision.point;
+        self.albedo = self.albedo.clone() + collision.material.albedo(collision);
+        self.object_id.get_or_insert(collision.object_id);
+        if self.material_id.is_none() {
+            self.material_id = scene.material_id(&collision.material);
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    fn depth(&self) -> f64 {
+        if self.hits == 0 {
+            f64::INFINITY
+        } else {
+            self.depth / f64::from(self.hits)
+        }
+    }
+
+    fn normal(&self) -> Vec3 {
+        if self.normal.is_near_zero() {
+            Vec3::default()
+        } else {
+            self.normal.unit()
+        }
+    }
+
+    fn position(&self) -> Point {
+        if self.hits == 0 {
+            Point::default()
+        } else {
+            self.position / f64::from(self.hits)
+        }
+    }
+
+    /// Averaged over every sample, so edges against the background blend into black.
+    fn albedo(&self) -> Color {
+        if self.samples == 0 {
+            Color::black()
+        } else {
+            self.albedo.clone() / f64::from(self.samples)
+        }
+    }
+}
+
+/// All AOVs of a frame, stored row by row from the top-left corner like a [`Framebuffer`].
+pub struct Aovs {
+    width: usize,
+    height: usize,
+    pixels: Vec<AovPixel>,
+}
+
+impl Aovs {
+    pub fn new(width: usize, height: usize, pixels: Vec<AovPixel>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "AOVs need exactly width * height pixels"
+        );
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[AovPixel] {
+        &self.pixels
+    }
+
+    fn value(&self, aov: Aov, pixel: &AovPixel) -> Color {
+        let vector = |v: Vec3| Color::new(v.x, v.y, v.z);
+        match aov {
+            Aov::Depth => Color::all(pixel.depth()),
+            Aov::Normal => vector(pixel.normal()),
+            Aov::Albedo => pixel.albedo(),
+            Aov::ObjectId => Color::all(pixel.object_id.map_or(0., |id| (id + 1) as f64)),
+            Aov::MaterialId => Color::all(pixel.material_id.map_or(0., |id| id as f64)),
+            Aov::Position => vector(pixel.position()),
+            Aov::SampleCount => Color::all(f64::from(pixel.samples)),
+        }
+    }
+
+    /// The pass's actual values, single-valued passes repeated in all three channels, for
+    /// the floating point formats.
+    pub fn raw(&self, aov: Aov) -> Framebuffer {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|pixel| self.value(aov, pixel))
+            .collect();
+        Framebuffer::from_pixels(self.width, self.height, pixels)
+    }
+
+    /// The pass squeezed into something viewable: near depths bright, normals and positions
+    /// as colors, a color per ID, sample counts as a heatmap from black up to yellow at the
+    /// most samples any pixel took. Meant to be written as is, without tone mapping.
+    pub fn visualize(&self, aov: Aov) -> Framebuffer {
+        let raw = self.raw(aov);
+        if aov == Aov::Albedo {
+            return raw;
+        }
+
+        // sample counts cover every pixel, the other passes only those that hit something
+        let covered: Vec<bool> = self
+            .pixels
+            .iter()
+            .map(|pixel| aov == Aov::SampleCount || pixel.hits > 0)
+            .collect();
+        let (low, high) = channel_range(raw.pixels(), &covered);
+        let normalize = |value: &Color| {
+            let scale = |v: f64, low: f64, high: f64| {
+                if high > low {
+                    (v - low) / (high - low)
+                } else {
+                    1.
+                }
+            };
+            Color::new(
+                scale(value.r, low.r, high.r),
+                scale(value.g, low.g, high.g),
+                scale(value.b, low.b, high.b),
+            )
+        };
+
+        let pixels = raw
+            .pixels()
+            .iter()
+            .zip(covered)
+            .map(|(value, covered)| {
+                if !covered {
+                    return Color::black();
+                }
+                let display = match aov {
+                    Aov::Depth => Color::all(1. - normalize(value).r),
+                    Aov::Normal => Color::new(
+                        0.5 + 0.5 * value.r,
+                        0.5 + 0.5 * value.g,
+                        0.5 + 0.5 * value.b,
+                    ),
+                    Aov::ObjectId | Aov::MaterialId => id_color(value.r as u64),
+                    Aov::SampleCount => heatmap(if high.r > 0. { value.r / high.r } else { 0. }),
+                    _ => normalize(value),
+                };
+                // the writers sRGB-encode what they are given, so undo that in advance
+                Color::new(
+                    srgb_to_linear(display.r),
+                    srgb_to_linear(display.g),
+                    srgb_to_linear(display.b),
+                )
+            })
+            .collect();
+        Framebuffer::from_pixels(self.width, self.height, pixels)
+    }
+
+    /// Adds the pass to an OpenEXR image under its conventional channel names.
+    pub fn add_to_exr(&self, image: ExrImage, aov: Aov) -> ExrImage {
+        let raw = self.raw(aov);
+        let component = |i: usize| -> Vec<f32> {
+            raw.pixels()
+                .iter()
+                .map(|c| [c.r, c.g, c.b][i] as f32)
+                .collect()
+        };
+        aov.exr_channels()
+            .iter()
+            .enumerate()
+            .fold(image, |image, (i, name)| image.channel(*name, component(i)))
+    }
+}
+
+/// Per-channel minimum and maximum over the covered pixels.
+fn channel_range(values: &[Color], covered: &[bool]) -> (Color, Color) {
+    let (mut low, mut high) = (Color::all(f64::INFINITY), Color::all(f64::NEG_INFINITY));
+    for (value, _) in values.iter().zip(covered).filter(|(_, &covered)| covered) {
+        low = Color::new(low.r.min(value.r), low.g.min(value.g), low.b.min(value.b));
+        high = Color::new(
+            high.r.max(value.r),
+            high.g.max(value.g),
+            high.b.max(value.b),
+        );
+    }
+    (low, high)
+}
+
+/// Roughly matplotlib's inferno colormap over `[0, 1]`, in sRGB.
+fn heatmap(t: f64) -> Color {
+    const STOPS: [[f64; 3]; 5] = [
+        [0., 0., 0.02],
+        [0.34, 0.06, 0.43],
+        [0.73, 0.21, 0.33],
+        [0.98, 0.55, 0.04],
+        [0.99, 1., 0.64],
+    ];
+    let scaled = t.clamp(0., 1.) * (STOPS.len() - 1) as f64;
+    let i = (scaled as usize).min(STOPS.len() - 2);
+    let f = scaled - i as f64;
+    let [r, g, b] = [0, 1, 2].map(|c| STOPS[i][c] * (1. - f) + STOPS[i + 1][c] * f);
+    Color::new(r, g, b)
+}
+
+/// A bright, well spread color for each ID, black for 0.
+fn id_color(id: u64) -> Color {
+    if id == 0 {
+        return Color::black();
+    }
+    let mut z = id.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z ^= z >> 27;
+    let channel = |shift: u32| 0.2 + 0.8 * f64::from((z >> shift) as u8) / 255.;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Camera, CollidableVec, Lambertian, Sphere, Triangle};
+
+    /// A sphere off to the side, listed first, and a plane at z = 0 facing +z.
+    fn plane_scene() -> Scene {
+        let sphere = Lambertian::new_arc(Color::all(0.5));
+        let plane = Lambertian::new_arc(Color::new(0.2, 0.4, 0.6));
+        let corners =
+            [(-5., -5.), (5., -5.), (5., 5.), (-5., 5.)].map(|(x, y)| Point::new(x, y, 0.));
+        let objects: CollidableVec = vec![
+            Sphere::boxed(Point::new(20., 0., 0.), 1., sphere),
+            Triangle::boxed(corners[0], corners[1], corners[2], plane.clone()),
+            Triangle::boxed(corners[0], corners[2], corners[3], plane),
+        ];
+        Scene::new(objects, Camera::new())
+    }
+
+    fn rgb(color: &Color) -> (f64, f64, f64) {
+        (color.r, color.g, color.b)
+    }
+
+    #[test]
+    fn passes_describe_the_first_hit() {
+        let scene = plane_scene();
+        // not normalized, so the depth has to account for the direction's length
+        let ray = Ray::new(Point::new(1., 2., 5.), Vec3::new(0., 0., -2.));
+        let mut hit = AovPixel::default();
+        for _ in 0..3 {
+            hit.add(
+                &ray,
+                scene.collide(&ray, 0.001, f64::INFINITY).as_ref(),
+                &scene,
+            );
+        }
+        let sky = Ray::new(Point::new(1., 2., 5.), Vec3::new(0., 0., 1.));
+        let mut miss = AovPixel::default();
+        miss.add(
+            &sky,
+            scene.collide(&sky, 0.001, f64::INFINITY).as_ref(),
+            &scene,
+        );
+
+        let aovs = Aovs::new(2, 1, vec![hit, miss]);
+        let pass = |aov| aovs.raw(aov);
+        assert_eq!(rgb(pass(Aov::Depth).get(0, 0)), (5., 5., 5.));
+        assert_eq!(pass(Aov::Depth).get(1, 0).r, f64::INFINITY);
+        assert_eq!(rgb(pass(Aov::Normal).get(0, 0)), (0., 0., 1.));
+        // averaged over the samples, so only up to rounding
+        let albedo = rgb(pass(Aov::Albedo).get(0, 0));
+        assert!(
+            (albedo.0 - 0.2).abs() < 1e-12
+                && (albedo.1 - 0.4).abs() < 1e-12
+                && (albedo.2 - 0.6).abs() < 1e-12
+        );
+        assert_eq!(rgb(pass(Aov::Position).get(0, 0)), (1., 2., 0.));
+        assert_eq!(pass(Aov::SampleCount).get(0, 0).r, 3.);
+
+        // the background is 0 in every pass but the sample count
+        for aov in [
+            Aov::Normal,
+            Aov::Albedo,
+            Aov::ObjectId,
+            Aov::MaterialId,
+            Aov::Position,
+        ] {
+            assert_eq!(rgb(pass(aov).get(1, 0)), (0., 0., 0.), "{aov:?}");
+        }
+        assert_eq!(pass(Aov::SampleCount).get(1, 0).r, 1.);
+    }
+
+    #[test]
+    fn ids_follow_the_scene_not_the_image() {
+        let scene = plane_scene();
+        let mut pixel = AovPixel::default();
+        let ray = Ray::new(Point::new(-1., 3., 5.), Vec3::new(0., 0., -1.));
+        pixel.add(
+            &ray,
+            scene.collide(&ray, 0.001, f64::INFINITY).as_ref(),
+            &scene,
+        );
+
+        // the plane shows up first in the image, but is listed after the sphere
+        let aovs = Aovs::new(1, 1, vec![pixel]);
+        assert_eq!(aovs.raw(Aov::MaterialId).get(0, 0).r, 2.);
+        let object_id = aovs.raw(Aov::ObjectId).get(0, 0).r;
+        assert!(object_id == 2. || object_id == 3., "object id {object_id}");
+
+        let sphere = Ray::new(Point::new(20., 0., 5.), Vec3::new(0., 0., -1.));
+        let hit = scene.collide(&sphere, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(scene.material_id(&hit.material), Some(1));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{load_image, rand, Color, Distribution2D, Framebuffer, ImageError, Vec3, WrapMode};
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+pub struct BackgroundSample {
+    pub direction: Vec3,
+    pub radiance: Color,
+    pub pdf: f64,
+}
+
+/// What a ray sees when it leaves the scene without hitting anything.
+pub trait Background: Send + Sync {
+    fn color(&self, direction: Vec3) -> Color;
+
+    /// Picks a direction in proportion to how much light arrives from it, for backgrounds
+    /// bright and uneven enough to be worth sampling as a light.
+    fn sample(&self) -> Option<BackgroundSample> {
+        None
+    }
+
+    /// Whether [`Background::sample`] is implemented, making the background one of the lights
+    /// the integrator picks from.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// Solid-angle density with which [`Background::sample`] returns `direction`.
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        0.
+    }
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+    pub fn new_arc(color: Color) -> Arc<Self> {
+        Arc::new(Self::new(color))
+    }
+}
+
+impl Background for SolidColor {
+    fn color(&self, _: Vec3) -> Color {
+        self.color.clone()
+    }
+}
+
+/// Blends from `bottom` straight down to `top` straight up.
+pub struct Gradient {
+    pub bottom: Color,
+    pub top: Color,
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self::new(Color::white(), Color::new(0.5, 0.7, 1.))
+    }
+}
+
+impl Gradient {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+    pub fn new_arc(bottom: Color, top: Color) -> Arc<Self> {
+        Arc::new(Self::new(bottom, top))
+    }
+}
+
+impl Background for Gradient {
+    fn color(&self, direction: Vec3) -> Color {
+        let t = 0.5 * (direction.unit().y + 1.);
+        Color::from((1. - t) * Vec3::from(self.bottom.clone()) + t * Vec3::from(self.top.clone()))
+    }
+}
+
+/// An equirectangular (latitude-longitude) image wrapped around the scene. The top row is
+/// straight up, and the horizontal center looks down -z before any rotation.
+pub struct LatLongMap {
+    image: Framebuffer,
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl LatLongMap {
+    pub fn new(width: usize, height: usize, texels: Vec<Color>) -> Self {
+        assert_eq!(
+            texels.len(),
+            width * height,
+            "a lat-long map needs exactly width * height texels"
+        );
+        assert!(width > 0 && height > 0, "a lat-long map cannot be empty");
+
+        // rows near the poles cover less solid angle, so they are sampled less often
+        let weights: Vec<f64> = texels
+            .iter()
+            .enumerate()
+            .map(|(i, texel)| {
+                let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+                texel.luminance().max(0.) * theta.sin()
+            })
+            .collect();
+
+        Self {
+            distribution: Distribution2D::new(&weights, width, height),
+            image: Framebuffer::from_pixels(width, height, texels),
+            rotation: 0.,
+            intensity: 1.,
+        }
+    }
+    pub fn new_arc(width: usize, height: usize, texels: Vec<Color>) -> Arc<Self> {
+        Arc::new(Self::new(width, height, texels))
+    }
+
+    pub fn from_image(image: Framebuffer) -> Self {
+        Self::new(image.width(), image.height(), image.pixels().to_vec())
+    }
+
+    /// Loads a panorama in any format [`load_image`] reads, ideally a high dynamic range one.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+        Ok(Self::from_image(load_image(path)?))
+    }
+
+    /// Spins the map counter-clockwise around the vertical axis.
+    #[must_use]
+    pub fn rotation(self, degrees: f64) -> Self {
+        LatLongMap {
+            rotation: degrees.to_radians(),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn intensity(self, intensity: f64) -> Self {
+        LatLongMap { intensity, ..self }
+    }
+
+    /// Maps a direction to image coordinates in `[0, 1)`, `v` growing downwards.
+    pub fn direction_to_uv(direction: Vec3) -> (f64, f64) {
+        let d = direction.unit();
+        let u = 0.5 + d.x.atan2(-d.z) / (2. * PI);
+        let v = d.y.clamp(-1., 1.).acos() / PI;
+        (u, v)
+    }
+
+    pub fn uv_to_direction(u: f64, v: f64) -> Vec3 {
+        let (phi, theta) = (2. * PI * (u - 0.5), PI * v);
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    /// Bilinear lookup, wrapping around horizontally and clamping at the poles.
+    pub fn sample_uv(&self, u: f64, v: f64) -> Color {
+        self.image
+            .sample_bilinear(u, v, WrapMode::Repeat, WrapMode::Clamp)
+    }
+}
+
+impl Background for LatLongMap {
+    fn color(&self, direction: Vec3) -> Color {
+        let (u, v) = Self::direction_to_uv(direction.rotate_y(-self.rotation));
+        self.sample_uv(u, v) * self.intensity
+    }
+
+    fn is_light(&self) -> bool {
+        true
+    }
+
+    fn sample(&self) -> Option<BackgroundSample> {
+        let ((u, v), pdf_uv) = self.distribution.sample(rand(), rand());
+        let sin_theta = (PI * v).sin();
+        if pdf_uv <= 0. || sin_theta <= 0. {
+            return None;
+        }
+
+        let direction = Self::uv_to_direction(u, v).rotate_y(self.rotation);
+        Some(BackgroundSample {
+            radiance: self.color(direction),
+            pdf: pdf_uv / (2. * PI * PI * sin_theta),
+            direction,
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = Self::direction_to_uv(direction.rotate_y(-self.rotation));
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        self.distribution.pdf(u, v) / (2. * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small panorama with a bright spot, rotated so the mapping is exercised too.
+    fn sunny() -> LatLongMap {
+        let (width, height) = (16, 8);
+        let texels = (0..width * height)
+            .map(|i| match (i % width, i / width) {
+                (5, 2) => Color::new(50., 40., 30.),
+                (x, _) => Color::all(0.1 + 0.05 * x as f64),
+            })
+            .collect();
+        LatLongMap::new(width, height, texels).rotation(30.)
+    }
+
+    /// Integrates `pdf` over the sphere on a fine grid of panorama coordinates.
+    fn solid_angle_integral(map: &LatLongMap) -> f64 {
+        let (nu, nv) = (320, 160);
+        let mut sum = 0.;
+        for i in 0..nu {
+            for j in 0..nv {
+                let (u, v) = ((i as f64 + 0.5) / nu as f64, (j as f64 + 0.5) / nv as f64);
+                let direction = LatLongMap::uv_to_direction(u, v).rotate_y(map.rotation);
+                let area = 2. * PI * PI * (PI * v).sin() / (nu * nv) as f64;
+                sum += map.pdf(direction) * area;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let integral = solid_angle_integral(&sunny());
+        assert!((integral - 1.).abs() < 1e-3, "pdf integrates to {integral}");
+    }
+
+    #[test]
+    fn samples_agree_with_the_pdf() {
+        let map = sunny();
+        for _ in 0..2000 {
+            let sample = map.sample().unwrap();
+            let pdf = map.pdf(sample.direction);
+            assert!(
+                (sample.pdf - pdf).abs() < 1e-6 * pdf,
+                "{} != {pdf}",
+                sample.pdf
+            );
+        }
+    }
+
+    #[test]
+    fn black_maps_are_sampled_uniformly() {
+        let map = LatLongMap::new(8, 4, vec![Color::black(); 32]);
+        let integral = solid_angle_integral(&map);
+        assert!((integral - 1.).abs() < 1e-3, "pdf integrates to {integral}");
+        let sample = map.sample().unwrap();
+        let (_, v) = LatLongMap::direction_to_uv(sample.direction);
+        assert!((sample.pdf - 1. / (2. * PI * PI * (PI * v).sin())).abs() < 1e-6 * sample.pdf);
+    }
+
+    #[test]
+    fn gradients_blend_from_bottom_to_top() {
+        let gradient = Gradient::new(Color::new(1., 0., 0.), Color::new(0., 0., 1.));
+        let rgb = |direction: Vec3| {
+            let color = gradient.color(direction);
+            (color.r, color.g, color.b)
+        };
+        assert_eq!(rgb(Vec3::new(0., 5., 0.)), (0., 0., 1.));
+        assert_eq!(rgb(Vec3::new(0., -2., 0.)), (1., 0., 0.));
+        assert_eq!(rgb(Vec3::new(3., 0., -4.)), (0.5, 0., 0.5));
+        assert!(!gradient.is_light());
+
+        let solid = SolidColor::new(Color::new(0.1, 0.2, 0.3));
+        let color = solid.color(Vec3::new(1., -1., 0.));
+        assert_eq!((color.r, color.g, color.b), (0.1, 0.2, 0.3));
+        assert!(solid.sample().is_none());
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{
+    Aabb, Collidable, CollidableVec, Collision, DynCollidable, Emitter, Material, Point, Ray,
+};
+use std::sync::Arc;
+
+const SAH_BINS: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f64 = 0.125;
+
+enum NodeKind {
+    Leaf {
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        left: usize,
+        right: usize,
+        axis: usize,
+    },
+}
+
+struct BvhNode {
+    bbox: Aabb,
+    kind: NodeKind,
+}
+
+struct BuildItem {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point,
+}
+
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    objects: CollidableVec,
+    unbounded: CollidableVec,
+    /// Where each of `objects` and then `unbounded` was in the list given to [`Bvh::new`].
+    ids: Vec<usize>,
+}
+
+impl Bvh {
+    #[must_use]
+    pub fn new(objects: CollidableVec) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = CollidableVec::new();
+        let mut unbounded_ids = Vec::new();
+        let mut items = Vec::new();
+
+        for (id, object) in objects.into_iter().enumerate() {
+            match object.bounding_box() {
+                Some(bbox) => {
+                    items.push(BuildItem {
+                        index: bounded.len(),
+                        bbox,
+                        centroid: bbox.centroid(),
+                    });
+                    bounded.push(Some((id, object)));
+                }
+                None => {
+                    unbounded.push(object);
+                    unbounded_ids.push(id);
+                }
+            }
+        }
+
+        let mut nodes = Vec::new();
+        if !items.is_empty() {
+            Self::build(&mut nodes, &mut items, 0);
+        }
+
+        let (mut ids, objects): (Vec<usize>, CollidableVec) = items
+            .iter()
+            .map(|item| bounded[item.index].take().unwrap())
+            .unzip();
+        ids.extend(unbounded_ids);
+
+        Self {
+            nodes,
+            objects,
+            unbounded,
+            ids,
+        }
+    }
+
+    #[must_use]
+    pub fn boxed(objects: CollidableVec) -> Box<Self> {
+        Box::new(Self::new(objects))
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len() + self.unbounded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn build(nodes: &mut Vec<BvhNode>, items: &mut [BuildItem], start: usize) -> usize {
+        let bbox = items
+            .iter()
+            .fold(Aabb::empty(), |acc, item| acc.surrounding(&item.bbox));
+        let centroid_bounds = items
+            .iter()
+            .fold(Aabb::empty(), |acc, item| acc.including(item.centroid));
+
+        let node_index = nodes.len();
+        nodes.push(BvhNode {
+            bbox,
+            kind: NodeKind::Leaf {
+                start,
+                count: items.len(),
+            },
+        });
+
+        if items.len() <= 2 {
+            return node_index;
+        }
+
+        let split = Self::sah_split(items, &bbox, &centroid_bounds);
+        let (mut axis, mut mid) = (centroid_bounds.longest_axis(), 0);
+        if let Some((split_axis, split_bin, cost)) = split {
+            if cost >= items.len() as f64 && items.len() <= MAX_LEAF_SIZE {
+                return node_index;
+            }
+            axis = split_axis;
+            mid = Self::partition(items, |item| {
+                Self::bin_of(item.centroid, &centroid_bounds, axis) <= split_bin
+            });
+        }
+
+        // fall back to an object median split when binning cannot separate the items
+        if mid == 0 || mid == items.len() {
+            if items.len() <= MAX_LEAF_SIZE {
+                return node_index;
+            }
+            items.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+            mid = items.len() / 2;
+        }
+
+        let (left_items, right_items) = items.split_at_mut(mid);
+        let left = Self::build(nodes, left_items, start);
+        let right = Self::build(nodes, right_items, start + mid);
+        nodes[node_index].kind = NodeKind::Interior { left, right, axis };
+        node_index
+    }
+
+    fn bin_of(centroid: Point, centroid_bounds: &Aabb, axis: usize) -> usize {
+        let offset = (centroid[axis] - centroid_bounds.min[axis]) / centroid_bounds.extent()[axis];
+        ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+    }
+
+    /// Returns the axis, last bin of the left half and the relative cost of the cheapest split.
+    fn sah_split(
+        items: &[BuildItem],
+        bbox: &Aabb,
+        centroid_bounds: &Aabb,
+    ) -> Option<(usize, usize, f64)> {
+        let mut best: Option<(usize, usize, f64)> = None;
+        let parent_area = bbox.surface_area();
+
+        for axis in 0..3 {
+            if centroid_bounds.extent()[axis] <= 0. {
+                continue;
+            }
+
+            let mut bins = [(Aabb::empty(), 0usize); SAH_BINS];
+            for item in items {
+                let bin = &mut bins[Self::bin_of(item.centroid, centroid_bounds, axis)];
+                bin.0 = bin.0.surrounding(&item.bbox);
+                bin.1 += 1;
+            }
+
+            let mut right_area = [0.; SAH_BINS];
+            let mut right_count = [0; SAH_BINS];
+            let mut acc = (Aabb::empty(), 0);
+            for i in (1..SAH_BINS).rev() {
+                acc = (acc.0.surrounding(&bins[i].0), acc.1 + bins[i].1);
+                right_area[i] = acc.0.surface_area();
+                right_count[i] = acc.1;
+            }
+
+            let mut acc = (Aabb::empty(), 0);
+            for split in 0..SAH_BINS - 1 {
+                acc = (acc.0.surrounding(&bins[split].0), acc.1 + bins[split].1);
+                if acc.1 == 0 || right_count[split + 1] == 0 {
+                    continue;
+                }
+                let cost = TRAVERSAL_COST
+                    + (acc.0.surface_area() * acc.1 as f64
+                        + right_area[split + 1] * right_count[split + 1] as f64)
+                        / parent_area;
+                if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best = Some((axis, split, cost));
+                }
+            }
+        }
+
+        best
+    }
+
+    fn partition(items: &mut [BuildItem], predicate: impl Fn(&BuildItem) -> bool) -> usize {
+        let mut mid = 0;
+        for i in 0..items.len() {
+            if predicate(&items[i]) {
+                items.swap(i, mid);
+                mid += 1;
+            }
+        }
+        mid
+    }
+}
+
+impl Collidable for Bvh {
+    fn collide(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Collision> {
+        // nested hierarchies tag their own hits first, so the outermost one has the last word
+        let unbounded_ids = &self.ids[self.objects.len()..];
+        let mut closest_col = None;
+        let mut closest = t_max;
+        for (item, &id) in self.unbounded.iter().zip(unbounded_ids) {
+            if let Some(collision) = item.collide(ray, t_min, closest) {
+                closest = collision.dist;
+                closest_col = Some(Collision {
+                    object_id: id,
+                    ..collision
+                });
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return closest_col;
+        }
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(index) = stack.pop() {
+            let node: &BvhNode = &self.nodes[index];
+            if !node.bbox.hit(ray, t_min, closest) {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { start, count } => {
+                    let items = self.objects[start..start + count].iter();
+                    for (item, &id) in items.zip(&self.ids[start..start + count]) {
+                        if let Some(collision) = item.collide(ray, t_min, closest) {
+                            closest = collision.dist;
+                            closest_col = Some(Collision {
+                                object_id: id,
+                                ..collision
+                            });
+                        }
+                    }
+                }
+                NodeKind::Interior { left, right, axis } => {
+                    // push the far child first so the near one is visited first
+                    if ray.direction[axis] < 0. {
+                        stack.push(left);
+                        stack.push(right);
+                    } else {
+                        stack.push(right);
+                        stack.push(left);
+                    }
+                }
+            }
+        }
+
+        closest_col
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.unbounded.is_empty() {
+            self.nodes.first().map(|node| node.bbox)
+        } else {
+            None
+        }
+    }
+
+    fn emitters(&self) -> Vec<Box<dyn Emitter>> {
+        let mut emitters = self.objects.emitters();
+        emitters.extend(self.unbounded.emitters());
+        emitters
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        // back in the order the objects were given, which the tree does not keep
+        let mut objects: Vec<(usize, &DynCollidable)> = self
+            .ids
+            .iter()
+            .copied()
+            .zip(self.objects.iter().chain(&self.unbounded))
+            .collect();
+        objects.sort_by_key(|&(id, _)| id);
+        objects
+            .into_iter()
+            .flat_map(|(_, object)| object.materials())
+            .collect()
+    }
+}
+
+impl From<CollidableVec> for Bvh {
+    fn from(objects: CollidableVec) -> Self {
+        Self::new(objects)
+    }
+}
+
+impl From<Bvh> for DynCollidable {
+    fn from(bvh: Bvh) -> Self {
+        Box::new(bvh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Color, Lambertian, Sphere, Vec3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_point(rng: &mut StdRng, extent: f64) -> Point {
+        Point::new(
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+        )
+    }
+
+    fn random_spheres(rng: &mut StdRng, count: usize) -> (CollidableVec, CollidableVec) {
+        let material = Lambertian::new_arc(Color::all(0.5));
+        let mut linear = CollidableVec::new();
+        let mut for_bvh = CollidableVec::new();
+        for _ in 0..count {
+            let center = random_point(rng, 20.);
+            let radius = rng.gen_range(0.05..1.5);
+            linear.push(Sphere::boxed(center, radius, material.clone()));
+            for_bvh.push(Sphere::boxed(center, radius, material.clone()));
+        }
+        (linear, for_bvh)
+    }
+
+    #[test]
+    fn empty_bvh_misses() {
+        let bvh = Bvh::new(CollidableVec::new());
+        let ray = Ray::new(Point::origin(), Vec3::from_z(1.));
+        assert!(bvh.is_empty());
+        assert!(bvh.collide(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn matches_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (linear, for_bvh) = random_spheres(&mut rng, 1000);
+        let bvh = Bvh::new(for_bvh);
+        assert_eq!(bvh.len(), 1000);
+
+        for _ in 0..5000 {
+            let origin = random_point(&mut rng, 25.);
+            let direction = random_point(&mut rng, 1.).unit();
+            let ray = Ray::new(origin, direction);
+            let expected = linear.collide(&ray, 0.001, f64::INFINITY);
+            let actual = bvh.collide(&ray, 0.001, f64::INFINITY);
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(e), Some(a)) => {
+                    assert_eq!(e.dist, a.dist);
+                    assert_eq!((e.point - a.point).len(), 0.);
+                }
+                (e, a) => panic!(
+                    "bvh disagreed with linear scan: {:?} vs {:?}",
+                    e.map(|c| c.dist),
+                    a.map(|c| c.dist)
+                ),
+            }
+        }
+    }
+}
@@ -4,7 +4,6 @@ use crate::{deg_to_rad, Point, Ray, Vec3};
 pub struct Camera {
     aspect_ratio: f64,
     view_h: f64,
-    view_w: f64,
     origin: Point,
     lower_left: Point,
     x_axis: Vec3,
@@ -15,8 +14,7 @@ pub struct Camera {
     lens_radius: f64,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
-    focus_dist: f64,
+    focus_dist: Option<f64>,
 }
 
 impl Default for Camera {
@@ -24,7 +22,6 @@ impl Default for Camera {
         Camera {
             aspect_ratio: 1.,
             view_h: 2.,
-            view_w: 2.,
             origin: Point::new(0., 0., 0.),
             x_axis: Vec3::from_x(2.),
             y_axis: Vec3::from_y(2.),
@@ -38,8 +35,7 @@ impl Default for Camera {
             lens_radius: 1.,
             u: Vec3::default(),
             v: Vec3::default(),
-            w: Vec3::default(),
-            focus_dist: Vec3::new(-1., 0., 1.).len(), //(lookfrom - lookat).len()
+            focus_dist: None,
         }
     }
 }
@@ -49,7 +45,9 @@ impl Camera {
         let w = (self.look_from - self.look_at).unit();
         let u = self.vup.cross(w).unit();
         let v = w.cross(u);
-        let focus_dist = (self.look_from - self.look_at).len();
+        let focus_dist = self
+            .focus_dist
+            .unwrap_or_else(|| (self.look_from - self.look_at).len());
         let origin = self.look_from;
         let view_w = self.aspect_ratio * self.view_h;
         let x_axis = view_w * u * focus_dist;
@@ -57,15 +55,12 @@ impl Camera {
         let lower_left = origin - x_axis / 2. - y_axis / 2. - w * focus_dist;
 
         Camera {
-            view_w,
             lower_left,
             x_axis,
             y_axis,
             origin,
-            w,
             u,
             v,
-            focus_dist,
             ..self
         }
     }
@@ -119,6 +114,11 @@ impl Camera {
         .update_dependent_components()
     }
 
+    #[must_use]
+    pub fn vup(self, vup: Vec3) -> Self {
+        Camera { vup, ..self }.update_dependent_components()
+    }
+
     #[must_use]
     pub fn lens_radius(self, radius: f64) -> Self {
         Camera {
@@ -128,10 +128,11 @@ impl Camera {
         .update_dependent_components()
     }
 
+    /// Distance to the plane in sharp focus; by default the distance to `look_at`.
     #[must_use]
     pub fn focus_dist(self, dist: f64) -> Self {
         Camera {
-            focus_dist: dist,
+            focus_dist: Some(dist),
             ..self
         }
         .update_dependent_components()
@@ -0,0 +1,323 @@
+use ray_tracer::{Aov, Color, ExrCompression, Integrator, PngFormat, TileOrder, ToneMapper};
+use std::fmt;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: render [options]
+
+options:
+    --width <pixels>     image width (default: scene file, else 800)
+    --height <pixels>    image height (default: scene file, else 800)
+    --spp <samples>      samples per pixel, or the most any pixel takes when adaptive
+                         (default: scene file, else 64)
+    --adaptive <error>   stop sampling a pixel once its relative standard error is below
+                         this, like 0.02; --aov sample_count shows where samples went
+    --min-spp <samples>  fewest samples an adaptive pixel takes (default: 16)
+    --depth <bounces>    maximum ray depth (default: scene file, else 50)
+    --threads <count>    worker threads (default: available cores)
+    --tile-size <pixels> edge length of the square tiles threads claim (default: 16)
+    --tile-order <order> scanline, spiral or hilbert (default: scanline)
+    --integrator <name>  naive, or nee to also sample lights directly (default: nee)
+    --out <path>         output image: .ppm or .png, or linear .pfm, .hdr or .exr
+                         (default: rayout/trace-<n>.ppm)
+    --png-format <fmt>   rgb8, rgba8, rgb16 or rgba16 for .png output (default: rgb8)
+    --exr-compression <method>
+                         none or zip for .exr output (default: zip)
+    --exposure <stops>   brighten (or, if negative, darken) .ppm and .png output
+    --tonemap <curve>    clamp, reinhard, extended-reinhard, aces or hable (default: clamp)
+    --white-point <luminance>
+                         what extended-reinhard maps to pure white (default: 4)
+    --white-balance <r,g,b>
+                         color that should come out neutral (default: 1,1,1)
+    --aov <passes>       comma-separated extra passes, or all: depth, normal, albedo,
+                         object_id, material_id, position, sample_count; stored as EXR
+                         channels, or else next to the output as <name>.<pass>.<ext>
+    --denoise <strength> smooth out noise, guided by the albedo, normal and depth passes;
+                         higher strengths remove more (try 1)
+    --seed <number>      seed for a reproducible image and random scene
+    --scene <path>       scene description file (default: built-in random scene)
+    -h, --help           print this message
+";
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub spp: Option<u32>,
+    pub adaptive: Option<f64>,
+    pub min_spp: Option<u32>,
+    pub depth: Option<i32>,
+    pub threads: Option<usize>,
+    pub tile_size: Option<usize>,
+    pub tile_order: Option<TileOrder>,
+    pub integrator: Option<Integrator>,
+    pub out: Option<PathBuf>,
+    pub png_format: Option<PngFormat>,
+    pub exr_compression: Option<ExrCompression>,
+    pub exposure: Option<f64>,
+    pub tonemap: Option<ToneMapper>,
+    pub white_point: Option<f64>,
+    pub white_balance: Option<Color>,
+    pub aovs: Vec<Aov>,
+    pub denoise: Option<f64>,
+    pub seed: Option<u64>,
+    pub scene: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Help,
+    Render(Box<Options>),
+}
+
+#[derive(Debug)]
+pub struct CliError(String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+fn positive<T: std::str::FromStr + Default + PartialOrd>(
+    flag: &str,
+    value: &str,
+) -> Result<T, CliError> {
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(CliError(format!(
+            "{flag} expects a positive integer, got '{value}'"
+        ))),
+    }
+}
+
+fn float(flag: &str, value: &str) -> Result<f64, CliError> {
+    match value.parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(n),
+        _ => Err(CliError(format!("{flag} expects a number, got '{value}'"))),
+    }
+}
+
+fn white_balance(flag: &str, value: &str) -> Result<Color, CliError> {
+    let channels: Vec<f64> = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .unwrap_or_default();
+    match channels[..] {
+        [r, g, b] if r > 0. && g > 0. && b > 0. => Ok(Color::new(r, g, b)),
+        _ => Err(CliError(format!(
+            "{flag} expects three positive numbers like 1,0.9,0.8, got '{value}'"
+        ))),
+    }
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_owned(), Some(value.into())),
+            _ => (arg, None),
+        };
+        if !flag.starts_with("--") {
+            return Err(CliError(format!("unexpected argument '{flag}'")));
+        }
+
+        let value = match inline_value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(CliError(format!("{flag} expects a value"))),
+        };
+
+        match flag.as_str() {
+            "--width" => options.width = Some(positive(&flag, &value)?),
+            "--height" => options.height = Some(positive(&flag, &value)?),
+            "--spp" => options.spp = Some(positive(&flag, &value)?),
+            "--adaptive" => match float(&flag, &value)? {
+                error if error > 0. => options.adaptive = Some(error),
+                _ => {
+                    return Err(CliError(format!(
+                        "--adaptive expects a positive number, got '{value}'"
+                    )))
+                }
+            },
+            "--min-spp" => options.min_spp = Some(positive(&flag, &value)?),
+            "--depth" => options.depth = Some(positive(&flag, &value)?),
+            "--threads" => options.threads = Some(positive(&flag, &value)?),
+            "--tile-size" => options.tile_size = Some(positive(&flag, &value)?),
+            "--tile-order" => {
+                options.tile_order = Some(
+                    value
+                        .parse()
+                        .map_err(|err| CliError(format!("{flag}: {err}")))?,
+                )
+            }
+            "--integrator" => {
+                options.integrator = Some(
+                    value
+                        .parse()
+                        .map_err(|err| CliError(format!("{flag}: {err}")))?,
+                )
+            }
+            "--seed" => {
+                options.seed = Some(value.parse().map_err(|_| {
+                    CliError(format!(
+                        "--seed expects a non-negative integer, got '{value}'"
+                    ))
+                })?)
+            }
+            "--out" => {
+                let path = PathBuf::from(value);
+                match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("ppm" | "png" | "pfm" | "hdr" | "exr") => options.out = Some(path),
+                    _ => {
+                        return Err(CliError(format!(
+                            "--out must name a .ppm, .png, .pfm, .hdr or .exr file, got '{}'",
+                            path.display()
+                        )))
+                    }
+                }
+            }
+            "--png-format" => {
+                options.png_format = Some(
+                    value
+                        .parse()
+                        .map_err(|err| CliError(format!("{flag}: {err}")))?,
+                )
+            }
+            "--exr-compression" => {
+                options.exr_compression = Some(
+                    value
+                        .parse()
+                        .map_err(|err| CliError(format!("{flag}: {err}")))?,
+                )
+            }
+            "--exposure" => options.exposure = Some(float(&flag, &value)?),
+            "--tonemap" => {
+                options.tonemap = Some(
+                    value
+                        .parse()
+                        .map_err(|err| CliError(format!("{flag}: {err}")))?,
+                )
+            }
+            "--white-point" => match float(&flag, &value)? {
+                white if white > 0. => options.white_point = Some(white),
+                _ => {
+                    return Err(CliError(format!(
+                        "--white-point expects a positive number, got '{value}'"
+                    )))
+                }
+            },
+            "--white-balance" => options.white_balance = Some(white_balance(&flag, &value)?),
+            "--aov" => {
+                options.aovs = if value == "all" {
+                    Aov::ALL.to_vec()
+                } else {
+                    value
+                        .split(',')
+                        .map(|name| name.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|err| CliError(format!("{flag}: {err}")))?
+                }
+            }
+            "--denoise" => match float(&flag, &value)? {
+                strength if strength > 0. => options.denoise = Some(strength),
+                _ => {
+                    return Err(CliError(format!(
+                        "--denoise expects a positive number, got '{value}'"
+                    )))
+                }
+            },
+            "--scene" => options.scene = Some(PathBuf::from(value)),
+            _ => return Err(CliError(format!("unknown option '{flag}'"))),
+        }
+    }
+
+    Ok(Command::Render(Box::new(options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        match parse_args(args.iter().map(|arg| arg.to_string())) {
+            Ok(Command::Render(options)) => Ok(*options),
+            Ok(Command::Help) => Err("help".to_owned()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    #[test]
+    fn values_may_follow_the_flag_or_an_equals_sign() {
+        let options = parse(&[
+            "--width=320",
+            "--spp",
+            "4",
+            "--out=a.png",
+            "--exposure=-1.5",
+        ])
+        .unwrap();
+        assert_eq!(options.width, Some(320));
+        assert_eq!(options.spp, Some(4));
+        assert_eq!(options.out, Some(PathBuf::from("a.png")));
+        assert_eq!(options.exposure, Some(-1.5));
+        assert_eq!(parse(&["--width", "8", "--help"]).unwrap_err(), "help");
+    }
+
+    #[test]
+    fn bad_arguments_are_explained() {
+        assert_eq!(parse(&["--width"]).unwrap_err(), "--width expects a value");
+        assert_eq!(
+            parse(&["--spp", "0"]).unwrap_err(),
+            "--spp expects a positive integer, got '0'"
+        );
+        assert_eq!(
+            parse(&["--spp=-3"]).unwrap_err(),
+            "--spp expects a positive integer, got '-3'"
+        );
+        assert_eq!(
+            parse(&["--frobnicate", "1"]).unwrap_err(),
+            "unknown option '--frobnicate'"
+        );
+        assert_eq!(
+            parse(&["scene.txt"]).unwrap_err(),
+            "unexpected argument 'scene.txt'"
+        );
+    }
+
+    #[test]
+    fn white_balance_needs_three_positive_numbers() {
+        let options = parse(&["--white-balance", "1, 0.9,0.8"]).unwrap();
+        let white = options.white_balance.unwrap();
+        assert_eq!((white.r, white.g, white.b), (1., 0.9, 0.8));
+        for value in ["1,0.9", "1,0.9,0.8,1", "1,x,0.8", "1,0,0.8", ""] {
+            assert_eq!(
+                parse(&["--white-balance", value]).unwrap_err(),
+                format!(
+                    "--white-balance expects three positive numbers like 1,0.9,0.8, got '{value}'"
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn output_extension_picks_the_format() {
+        for name in ["a.ppm", "a.png", "a.pfm", "a.hdr", "a.exr"] {
+            assert!(parse(&["--out", name]).is_ok());
+        }
+        for name in ["a.jpg", "a", "a.PNG"] {
+            assert_eq!(
+                parse(&["--out", name]).unwrap_err(),
+                format!("--out must name a .ppm, .png, .pfm, .hdr or .exr file, got '{name}'")
+            );
+        }
+    }
+}
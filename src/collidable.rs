@@ -1,8 +1,25 @@
-use crate::{Collision, Facing, Material, Point, Ray, WORLD};
+use crate::{
+    cone_pdf, sample_cone, Aabb, Collision, Emitter, Facing, LightSample, Material, Point, Ray,
+    Vec3,
+};
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 pub trait Collidable {
     fn collide(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Collision>;
+
+    /// `None` for objects without finite bounds, which acceleration structures test separately.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Emissive surfaces inside this object, for the integrator to sample directly.
+    fn emitters(&self) -> Vec<Box<dyn Emitter>> {
+        Vec::new()
+    }
+
+    /// Materials of the surfaces inside this object, in the order they were listed.
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        Vec::new()
+    }
 }
 
 pub type DynCollidable = Box<dyn Collidable + Send + Sync>;
@@ -22,10 +39,20 @@ impl Collidable for CollidableVec {
 
         closest_col
     }
-}
 
-pub fn add_to_world(object: DynCollidable) {
-    WORLD.write().unwrap().push(object);
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.iter().try_fold(Aabb::empty(), |acc, item| {
+            item.bounding_box().map(|bbox| acc.surrounding(&bbox))
+        })
+    }
+
+    fn emitters(&self) -> Vec<Box<dyn Emitter>> {
+        self.iter().flat_map(|item| item.emitters()).collect()
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        self.iter().flat_map(|item| item.materials()).collect()
+    }
 }
 
 pub struct Sphere {
@@ -74,15 +101,119 @@ impl Collidable for Sphere {
 
             let p = ray.at(root);
             let outward_normal = (p - self.center) / self.radius;
+            let (u, v) = Sphere::uv(outward_normal);
             let mut collision = Collision::new(
                 p,
                 outward_normal,
                 root,
                 Facing::Front,
                 self.material.clone(),
-            );
+            )
+            .with_uv(u, v);
             collision.set_face_normal(ray, outward_normal);
             Some(collision)
         }
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Point::all(self.radius.abs());
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    fn emitters(&self) -> Vec<Box<dyn Emitter>> {
+        if !self.material.is_emissive() {
+            return Vec::new();
+        }
+        vec![Box::new(Sphere::new(
+            self.center,
+            self.radius,
+            self.material.clone(),
+        ))]
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.material.clone()]
+    }
+}
+
+impl Sphere {
+    /// Spherical mapping of a point on the unit sphere: `u` runs around the vertical axis
+    /// starting at -x, `v` from the south pole to the north pole.
+    pub fn uv(p: Point) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1., 1.).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2. * PI), theta / PI)
+    }
+
+    /// Cosine of the half-angle the sphere subtends from `origin`, `None` from inside it.
+    fn cos_subtended(&self, origin: Point) -> Option<f64> {
+        let dist_sq = (self.center - origin).len_sq();
+        let radius_sq = self.radius * self.radius;
+        (dist_sq > radius_sq).then(|| (1. - radius_sq / dist_sq).sqrt())
+    }
+}
+
+impl Emitter for Sphere {
+    fn sample(&self, origin: Point) -> Option<LightSample> {
+        let cos_max = self.cos_subtended(origin)?;
+        Some(LightSample {
+            direction: sample_cone((self.center - origin).unit(), cos_max),
+            pdf: cone_pdf(cos_max),
+        })
+    }
+
+    fn pdf(&self, origin: Point, direction: Vec3) -> f64 {
+        match self.cos_subtended(origin) {
+            Some(cos_max)
+                if self
+                    .collide(&Ray::new(origin, direction), 0.001, f64::INFINITY)
+                    .is_some() =>
+            {
+                cone_pdf(cos_max)
+            }
+            _ => 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Color, Lambertian};
+
+    #[test]
+    fn sphere_uvs_run_from_pole_to_pole() {
+        let expected = [
+            (Vec3::new(0., -1., 0.), (0.5, 0.)),
+            (Vec3::new(0., 1., 0.), (0.5, 1.)),
+            (Vec3::new(-1., 0., 0.), (0., 0.5)),
+            (Vec3::new(0., 0., 1.), (0.25, 0.5)),
+            (Vec3::new(1., 0., 0.), (0.5, 0.5)),
+            (Vec3::new(0., 0., -1.), (0.75, 0.5)),
+        ];
+        for (p, (u, v)) in expected {
+            let uv = Sphere::uv(p);
+            // u is arbitrary at the poles themselves
+            if v != 0. && v != 1. {
+                assert!((uv.0 - u).abs() < 1e-12, "u at {p:?}: {uv:?}");
+            }
+            assert!((uv.1 - v).abs() < 1e-12, "v at {p:?}: {uv:?}");
+        }
+    }
+
+    #[test]
+    fn hits_carry_the_uv_of_the_point_hit() {
+        let sphere = Sphere::new(
+            Point::new(1., 2., 3.),
+            2.,
+            Lambertian::new_arc(Color::white()),
+        );
+        let from_above = Ray::new(Point::new(1., 10., 3.), Vec3::new(0., -1., 0.));
+        let collision = sphere.collide(&from_above, 0.001, f64::INFINITY).unwrap();
+        assert!((collision.uv.1 - 1.).abs() < 1e-12);
+
+        let from_front = Ray::new(Point::new(1., 2., 10.), Vec3::new(0., 0., -1.));
+        let collision = sphere.collide(&from_front, 0.001, f64::INFINITY).unwrap();
+        assert!((collision.uv.0 - 0.25).abs() < 1e-12 && (collision.uv.1 - 0.5).abs() < 1e-12);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{clamp, rand, rand_range, Point};
+use crate::{clamp, linear_to_srgb, rand, rand_range, Point};
 use std::ops::Add;
 use std::{fs::File, io::Write};
 
@@ -44,18 +44,25 @@ impl Color {
         )
     }
 
+    /// Relative luminance of linear Rec. 709 primaries.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// sRGB-encoded channels clamped to `[0, 1]`, as stored in integer image files.
     #[must_use]
-    pub fn as_output(&self, samples: u32) -> Vec<u8> {
-        let scale = 1. / f64::from(samples);
-        let r = f64::sqrt(self.r * scale);
-        let g = f64::sqrt(self.g * scale);
-        let b = f64::sqrt(self.b * scale);
+    pub fn encoded(&self) -> [f64; 3] {
+        [self.r, self.g, self.b].map(|c| linear_to_srgb(clamp(c, 0., 1.)))
+    }
 
+    #[must_use]
+    pub fn as_output(&self, samples: u32) -> Vec<u8> {
+        let [r, g, b] = (self.clone() / f64::from(samples)).encoded();
         format!(
             "{} {} {}\n",
-            (clamp(r, 0., 0.999) * 256.) as u8,
-            (clamp(g, 0., 0.999) * 256.) as u8,
-            (clamp(b, 0., 0.999) * 256.) as u8
+            (r * 256.).min(255.) as u8,
+            (g * 256.).min(255.) as u8,
+            (b * 256.).min(255.) as u8
         )
         .as_bytes()
         .to_owned()
@@ -79,6 +86,20 @@ impl std::ops::Mul for Color {
     }
 }
 
+impl std::ops::Mul<f64> for Color {
+    type Output = Self;
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl std::ops::Div<f64> for Color {
+    type Output = Self;
+    fn div(self, rhs: f64) -> Self::Output {
+        self * (1. / rhs)
+    }
+}
+
 impl From<Point> for Color {
     fn from(p: Point) -> Self {
         Color::new(p.x, p.y, p.z)
@@ -0,0 +1,388 @@
+use crate::{Aov, Aovs, Color, Framebuffer};
+use std::thread;
+
+/// Noise-free images that tell the denoiser where real edges are, so it can smooth
+/// everything else. Any of them may be left out.
+#[derive(Default)]
+pub struct Guides {
+    pub albedo: Option<Framebuffer>,
+    pub normal: Option<Framebuffer>,
+    /// Distance from the camera, infinite where nothing was hit.
+    pub depth: Option<Framebuffer>,
+}
+
+impl Guides {
+    pub fn from_aovs(aovs: &Aovs) -> Self {
+        Self {
+            albedo: Some(aovs.raw(Aov::Albedo)),
+            normal: Some(aovs.raw(Aov::Normal)),
+            depth: Some(aovs.raw(Aov::Depth)),
+        }
+    }
+}
+
+/// Edge-aware post-process for renders with too few samples: non-local means, where pixels
+/// with similar looking neighborhoods are averaged, with extra weights that keep pixels from
+/// mixing across albedo, normal or depth edges.
+#[derive(Debug, Clone, Copy)]
+pub struct Denoiser {
+    radius: usize,
+    patch_radius: usize,
+    strength: f64,
+    albedo_sigma: f64,
+    normal_sigma: f64,
+    depth_sigma: f64,
+    threads: usize,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            radius: 7,
+            patch_radius: 1,
+            strength: 1.,
+            albedo_sigma: 0.1,
+            normal_sigma: 0.3,
+            depth_sigma: 0.05,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// How far apart two tone-compressed colors can be at strength 1 and still be averaged.
+const COLOR_SIGMA: f64 = 0.12;
+
+impl Denoiser {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many pixels away, in each direction, neighbors are gathered from.
+    #[must_use]
+    pub fn radius(self, radius: usize) -> Self {
+        Denoiser { radius, ..self }
+    }
+
+    /// Size of the neighborhoods compared between pixels; 0 compares single pixels, which
+    /// makes this a joint bilateral filter.
+    #[must_use]
+    pub fn patch_radius(self, patch_radius: usize) -> Self {
+        Denoiser {
+            patch_radius,
+            ..self
+        }
+    }
+
+    /// Scales how different two colors may be and still get averaged: higher removes more
+    /// noise, and more detail with it.
+    #[must_use]
+    pub fn strength(self, strength: f64) -> Self {
+        assert!(strength > 0., "denoising strength must be positive");
+        Denoiser { strength, ..self }
+    }
+
+    #[must_use]
+    pub fn albedo_sigma(self, albedo_sigma: f64) -> Self {
+        Denoiser {
+            albedo_sigma,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn normal_sigma(self, normal_sigma: f64) -> Self {
+        Denoiser {
+            normal_sigma,
+            ..self
+        }
+    }
+
+    /// Relative depth difference, as a fraction of the farther depth.
+    #[must_use]
+    pub fn depth_sigma(self, depth_sigma: f64) -> Self {
+        Denoiser {
+            depth_sigma,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn threads(self, threads: usize) -> Self {
+        Denoiser { threads, ..self }
+    }
+
+    pub fn denoise(&self, image: &Framebuffer, guides: &Guides) -> Framebuffer {
+        let (width, height) = (image.width(), image.height());
+        for guide in [&guides.albedo, &guides.normal, &guides.depth]
+            .into_iter()
+            .flatten()
+        {
+            assert!(
+                guide.width() == width && guide.height() == height,
+                "denoising guides must match the image's size"
+            );
+        }
+        if width == 0 || height == 0 {
+            return Framebuffer::new(width, height);
+        }
+
+        let source = suppress_fireflies(image);
+        let image = &source;
+
+        // compare colors after squeezing them like a tone curve would, so bright highlights
+        // don't make every difference elsewhere look small
+        let compressed: Vec<[f64; 3]> = image
+            .pixels()
+            .iter()
+            .map(|c| [c.r, c.g, c.b].map(|v| v.max(0.) / (1. + v.max(0.))))
+            .collect();
+        let channels = |guide: &Option<Framebuffer>| {
+            guide
+                .as_ref()
+                .map(|guide| guide.pixels().iter().map(|c| [c.r, c.g, c.b]).collect())
+        };
+        let albedo: Option<Vec<[f64; 3]>> = channels(&guides.albedo);
+        let normal: Option<Vec<[f64; 3]>> = channels(&guides.normal);
+        let depth: Option<Vec<f64>> = guides
+            .depth
+            .as_ref()
+            .map(|depth| depth.pixels().iter().map(|c| c.r).collect());
+
+        let sigma_spatial = (self.radius as f64 / 2.).max(0.5);
+        let color_sigma = COLOR_SIGMA * self.strength;
+        let weight = |p: usize, q: usize, dx: isize, dy: isize| {
+            let (px, py) = (p % width, p / width);
+            let (qx, qy) = (q % width, q / width);
+            let mut exponent = (dx * dx + dy * dy) as f64 / (2. * sigma_spatial * sigma_spatial);
+            exponent += patch_distance(
+                &compressed,
+                width,
+                height,
+                self.patch_radius,
+                (px, py),
+                (qx, qy),
+            ) / (2. * color_sigma * color_sigma);
+            if let Some(albedo) = &albedo {
+                exponent += distance_sq(&albedo[p], &albedo[q])
+                    / (2. * self.albedo_sigma * self.albedo_sigma);
+            }
+            if let Some(normal) = &normal {
+                exponent += distance_sq(&normal[p], &normal[q])
+                    / (2. * self.normal_sigma * self.normal_sigma);
+            }
+            if let Some(depth) = &depth {
+                let (a, b) = (depth[p], depth[q]);
+                // misses only blend with other misses
+                let relative = match (a.is_finite(), b.is_finite()) {
+                    (true, true) if a.max(b) > 0. => (a - b).abs() / a.max(b),
+                    (false, false) | (true, true) => 0.,
+                    _ => return 0.,
+                };
+                exponent += relative * relative / (2. * self.depth_sigma * self.depth_sigma);
+            }
+            (-exponent).exp()
+        };
+
+        let radius = self.radius as isize;
+        let filter_pixel = |p: usize| {
+            let (x, y) = ((p % width) as isize, (p / width) as isize);
+            let (mut sum, mut total) = (Color::black(), 0.);
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let (qx, qy) = (x + dx, y + dy);
+                    if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                        continue;
+                    }
+                    let q = qy as usize * width + qx as usize;
+                    let w = weight(p, q, dx, dy);
+                    sum = sum + image.pixels()[q].clone() * w;
+                    total += w;
+                }
+            }
+            // the center always weighs 1, so the total can't be zero
+            sum / total
+        };
+
+        let mut pixels = vec![Color::black(); width * height];
+        let rows_per_thread = height.div_ceil(self.threads.clamp(1, height));
+        thread::scope(|s| {
+            for (chunk, rows) in pixels.chunks_mut(rows_per_thread * width).enumerate() {
+                let filter_pixel = &filter_pixel;
+                s.spawn(move || {
+                    let start = chunk * rows_per_thread * width;
+                    for (i, pixel) in rows.iter_mut().enumerate() {
+                        *pixel = filter_pixel(start + i);
+                    }
+                });
+            }
+        });
+        Framebuffer::from_pixels(width, height, pixels)
+    }
+}
+
+/// How many times brighter than all its neighbors a pixel can be before it counts as a
+/// firefly.
+const FIREFLY_RATIO: f64 = 4.;
+
+/// Lone pixels far brighter than everything around them match no other neighborhood, so
+/// nothing would average them away; this darkens them to their brightest neighbor instead.
+fn suppress_fireflies(image: &Framebuffer) -> Framebuffer {
+    let (width, height) = (image.width(), image.height());
+    let mut result = image.clone();
+    for y in 0..height {
+        for x in 0..width {
+            let brightest = (y.saturating_sub(1)..(y + 2).min(height))
+                .flat_map(|ny| (x.saturating_sub(1)..(x + 2).min(width)).map(move |nx| (nx, ny)))
+                .filter(|&neighbor| neighbor != (x, y))
+                .map(|(nx, ny)| image.get(nx, ny).luminance())
+                .fold(0., f64::max);
+            let pixel = image.get(x, y);
+            let luminance = pixel.luminance();
+            if luminance > FIREFLY_RATIO * brightest + 1e-3 {
+                result.set(x, y, pixel.clone() * (brightest / luminance));
+            }
+        }
+    }
+    result
+}
+
+fn distance_sq(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+/// Mean squared difference between the patches around `p` and `q`, with coordinates past
+/// the border clamped to it.
+fn patch_distance(
+    values: &[[f64; 3]],
+    width: usize,
+    height: usize,
+    patch_radius: usize,
+    p: (usize, usize),
+    q: (usize, usize),
+) -> f64 {
+    let r = patch_radius as isize;
+    let clamp = |v: usize, offset: isize, len: usize| {
+        (v as isize + offset).clamp(0, len as isize - 1) as usize
+    };
+    let mut sum = 0.;
+    for oy in -r..=r {
+        for ox in -r..=r {
+            let a = clamp(p.1, oy, height) * width + clamp(p.0, ox, width);
+            let b = clamp(q.1, oy, height) * width + clamp(q.0, ox, width);
+            sum += distance_sq(&values[a], &values[b]);
+        }
+    }
+    sum / ((2 * r + 1) * (2 * r + 1)) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const SIZE: usize = 48;
+
+    /// A dim left half and a bright right half of different materials facing different
+    /// ways, plus the guides a renderer would have produced for them.
+    fn two_halves() -> (Framebuffer, Guides) {
+        let image = |left: Color, right: Color| {
+            let pixels = (0..SIZE * SIZE)
+                .map(|i| {
+                    if i % SIZE < SIZE / 2 {
+                        left.clone()
+                    } else {
+                        right.clone()
+                    }
+                })
+                .collect();
+            Framebuffer::from_pixels(SIZE, SIZE, pixels)
+        };
+        let clean = image(Color::new(0.2, 0.1, 0.05), Color::new(0.3, 0.6, 0.9));
+        let guides = Guides {
+            albedo: Some(image(Color::new(0.8, 0.4, 0.2), Color::new(0.3, 0.6, 0.9))),
+            normal: Some(image(Color::new(1., 0., 0.), Color::new(0., 1., 0.))),
+            depth: Some(image(Color::all(3.), Color::all(3.))),
+        };
+        (clean, guides)
+    }
+
+    fn add_noise(image: &Framebuffer, amount: f64) -> Framebuffer {
+        let mut rng = StdRng::seed_from_u64(7);
+        let pixels = image
+            .pixels()
+            .iter()
+            .map(|c| {
+                let mut noisy = || rng.gen_range(1. - amount..1. + amount);
+                Color::new(c.r * noisy(), c.g * noisy(), c.b * noisy())
+            })
+            .collect();
+        Framebuffer::from_pixels(image.width(), image.height(), pixels)
+    }
+
+    fn squared_error(a: &Framebuffer, b: &Framebuffer, columns: impl Fn(usize) -> bool) -> f64 {
+        a.pixels()
+            .iter()
+            .zip(b.pixels())
+            .enumerate()
+            .filter(|(i, _)| columns(i % SIZE))
+            .map(|(_, (a, b))| distance_sq(&[a.r, a.g, a.b], &[b.r, b.g, b.b]))
+            .sum()
+    }
+
+    #[test]
+    fn removes_noise_without_blurring_edges() {
+        let (clean, guides) = two_halves();
+        let noisy = add_noise(&clean, 0.5);
+        let denoised = Denoiser::new().threads(3).denoise(&noisy, &guides);
+
+        let everywhere = |_| true;
+        assert!(
+            squared_error(&denoised, &clean, everywhere)
+                < squared_error(&noisy, &clean, everywhere) / 10.
+        );
+        // right next to the edge both sides keep their own color
+        for y in 0..SIZE {
+            let left = denoised.get(SIZE / 2 - 1, y);
+            let right = denoised.get(SIZE / 2, y);
+            assert!((left.b - 0.05).abs() < 0.02, "left bled: {left:?}");
+            assert!((right.b - 0.9).abs() < 0.2, "right bled: {right:?}");
+        }
+    }
+
+    #[test]
+    fn guides_keep_edges_the_colors_alone_would_miss() {
+        // the same color on both sides, only the guides know there are two surfaces
+        let (_, guides) = two_halves();
+        let (mut clean, _) = two_halves();
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let shade = if x < SIZE / 2 { 0.4 } else { 0.5 };
+                clean.set(x, y, Color::all(shade));
+            }
+        }
+        let noisy = add_noise(&clean, 0.5);
+        let near_edge = |x: usize| x.abs_diff(SIZE / 2) <= 2;
+        let unguided = Denoiser::new().denoise(&noisy, &Guides::default());
+        let guided = Denoiser::new().denoise(&noisy, &guides);
+        assert!(
+            squared_error(&guided, &clean, near_edge)
+                < squared_error(&unguided, &clean, near_edge) / 2.
+        );
+    }
+
+    #[test]
+    fn fireflies_are_removed() {
+        let (mut image, guides) = two_halves();
+        image.set(10, 10, Color::all(500.));
+        let denoised = Denoiser::new().denoise(&image, &guides);
+        assert!(denoised.get(10, 10).r < 0.25);
+    }
+
+    #[test]
+    fn clean_images_pass_through() {
+        let (clean, guides) = two_halves();
+        let denoised = Denoiser::new().denoise(&clean, &guides);
+        assert!(squared_error(&denoised, &clean, |_| true) < 1e-20);
+    }
+}
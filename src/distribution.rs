@@ -0,0 +1,179 @@
+/// A piecewise-constant density over `[0, 1)` built from non-negative function values.
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1].max(0.) / n as f64;
+        }
+
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            // an all-zero function degrades to uniform sampling
+            *c = if integral > 0. {
+                *c / integral
+            } else {
+                i as f64 / n as f64
+            };
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps a uniform `u` to `(x, pdf(x), bucket index)`.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.len();
+        let offset = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(n - 1);
+
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0. {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.
+        };
+        let x = ((offset as f64 + du) / n as f64).min(1. - f64::EPSILON);
+        (x, self.pdf_at(offset), offset)
+    }
+
+    fn pdf_at(&self, index: usize) -> f64 {
+        if self.integral > 0. {
+            self.func[index].max(0.) / self.integral
+        } else {
+            1.
+        }
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        let index = ((x * self.len() as f64) as usize).min(self.len() - 1);
+        self.pdf_at(index)
+    }
+}
+
+/// A piecewise-constant density over `[0, 1)^2`, sampled row first, then within the row.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `width * height` values stored row by row.
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        let conditional: Vec<_> = func
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+        Self {
+            conditional,
+            marginal,
+        }
+    }
+
+    /// Maps two uniform numbers to `((u, v), pdf(u, v))`.
+    pub fn sample(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u2);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u1);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let rows = self.conditional.len();
+        let row = ((v * rows as f64) as usize).min(rows - 1);
+        self.marginal.pdf(v) * self.conditional[row].pdf(u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 7;
+    const HEIGHT: usize = 5;
+
+    /// An uneven function with a few empty cells and a whole empty row.
+    fn lumpy() -> Vec<f64> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| match (i % WIDTH, i / WIDTH) {
+                (_, 2) | (3, _) => 0.,
+                (x, y) => 1. + (x * x + 3 * y) as f64,
+            })
+            .collect()
+    }
+
+    /// The pdf at the center of every cell, each cell covering `1 / (WIDTH * HEIGHT)`.
+    fn cell_pdfs(distribution: &Distribution2D) -> Vec<f64> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| {
+                let u = ((i % WIDTH) as f64 + 0.5) / WIDTH as f64;
+                let v = ((i / WIDTH) as f64 + 0.5) / HEIGHT as f64;
+                distribution.pdf(u, v)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let distribution = Distribution2D::new(&lumpy(), WIDTH, HEIGHT);
+        let integral: f64 = cell_pdfs(&distribution).iter().sum::<f64>() / (WIDTH * HEIGHT) as f64;
+        assert!(
+            (integral - 1.).abs() < 1e-12,
+            "pdf integrates to {integral}"
+        );
+    }
+
+    #[test]
+    fn samples_agree_with_the_pdf() {
+        let distribution = Distribution2D::new(&lumpy(), WIDTH, HEIGHT);
+        let steps = 97;
+        for i in 0..steps {
+            for j in 0..steps {
+                let (u1, u2) = (
+                    (i as f64 + 0.5) / steps as f64,
+                    (j as f64 + 0.5) / steps as f64,
+                );
+                let ((u, v), pdf) = distribution.sample(u1, u2);
+                assert!((0. ..1.).contains(&u) && (0. ..1.).contains(&v));
+                assert!(pdf > 0., "sampled the empty cell at ({u}, {v})");
+                assert!((pdf - distribution.pdf(u, v)).abs() < 1e-9 * pdf);
+            }
+        }
+    }
+
+    #[test]
+    fn all_zero_functions_sample_uniformly() {
+        let distribution = Distribution2D::new(&[0.; WIDTH * HEIGHT], WIDTH, HEIGHT);
+        assert!(cell_pdfs(&distribution).iter().all(|&pdf| pdf == 1.));
+        for (u1, u2) in [(0.1, 0.9), (0.5, 0.5), (0.93, 0.02)] {
+            let ((u, v), pdf) = distribution.sample(u1, u2);
+            assert!((u - u1).abs() < 1e-12 && (v - u2).abs() < 1e-12);
+            assert_eq!(pdf, 1.);
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{zlib_compress, Color, Framebuffer};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+/// File format version 2, single-part scanline.
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExrCompression {
+    None,
+    /// Deflate over blocks of 16 scanlines, lossless.
+    #[default]
+    Zip,
+}
+
+impl FromStr for ExrCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ExrCompression::None),
+            "zip" => Ok(ExrCompression::Zip),
+            _ => Err(format!(
+                "unknown EXR compression '{s}', expected none or zip"
+            )),
+        }
+    }
+}
+
+impl ExrCompression {
+    fn id(self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines_per_chunk(self) -> usize {
+        match self {
+            ExrCompression::None => 1,
+            ExrCompression::Zip => 16,
+        }
+    }
+}
+
+/// One named plane of 32-bit float values, stored row by row from the top-left corner.
+#[derive(Debug, Clone)]
+pub struct ExrChannel {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+/// An OpenEXR image with any number of named channels. Names with a dot, such as
+/// `albedo.R`, show up as separate layers in compositing tools.
+#[derive(Debug, Clone)]
+pub struct ExrImage {
+    width: usize,
+    height: usize,
+    channels: Vec<ExrChannel>,
+    compression: ExrCompression,
+}
+
+impl ExrImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            channels: Vec::new(),
+            compression: ExrCompression::default(),
+        }
+    }
+
+    /// The framebuffer as the image's main `R`, `G` and `B` channels.
+    pub fn from_framebuffer(image: &Framebuffer) -> Self {
+        Self::new(image.width(), image.height()).layer("", image)
+    }
+
+    #[must_use]
+    pub fn compression(self, compression: ExrCompression) -> Self {
+        ExrImage {
+            compression,
+            ..self
+        }
+    }
+
+    /// Adds a channel, replacing any earlier one with the same name.
+    #[must_use]
+    pub fn channel(mut self, name: impl Into<String>, values: Vec<f32>) -> Self {
+        let name = name.into();
+        assert_eq!(
+            values.len(),
+            self.width * self.height,
+            "EXR channel '{name}' needs exactly width * height values"
+        );
+        self.channels.retain(|channel| channel.name != name);
+        self.channels.push(ExrChannel { name, values });
+        self
+    }
+
+    /// Adds the colors as `<layer>.R`, `<layer>.G` and `<layer>.B`, or plain `R`, `G` and `B`
+    /// for an empty layer name.
+    #[must_use]
+    pub fn layer(self, layer: &str, image: &Framebuffer) -> Self {
+        let prefix = if layer.is_empty() {
+            String::new()
+        } else {
+            format!("{layer}.")
+        };
+        let plane = |f: fn(&Color) -> f64| image.pixels().iter().map(|c| f(c) as f32).collect();
+        self.channel(format!("{prefix}R"), plane(|c| c.r))
+            .channel(format!("{prefix}G"), plane(|c| c.g))
+            .channel(format!("{prefix}B"), plane(|c| c.b))
+    }
+
+    pub fn channels(&self) -> &[ExrChannel] {
+        &self.channels
+    }
+
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        // readers expect the channel list, and so the data in each scanline, sorted by name
+        let mut channels: Vec<&ExrChannel> = self.channels.iter().collect();
+        channels.sort_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+
+        let header = self.header(&channels);
+        let lines = self.compression.lines_per_chunk();
+        let chunks: Vec<Vec<u8>> = (0..self.height)
+            .step_by(lines)
+            .map(|y| self.chunk(&channels, y, lines.min(self.height - y)))
+            .collect();
+
+        // each chunk is found through a table of absolute file offsets
+        let mut offset = (MAGIC.len() + VERSION.len() + header.len() + 8 * chunks.len()) as u64;
+        out.write_all(&MAGIC)?;
+        out.write_all(&VERSION)?;
+        out.write_all(&header)?;
+        for chunk in &chunks {
+            out.write_all(&offset.to_le_bytes())?;
+            offset += chunk.len() as u64;
+        }
+        for chunk in &chunks {
+            out.write_all(chunk)?;
+        }
+        out.flush()
+    }
+
+    fn header(&self, channels: &[&ExrChannel]) -> Vec<u8> {
+        let mut channel_list = Vec::new();
+        for channel in channels {
+            channel_list.extend(channel.name.as_bytes());
+            channel_list.push(0);
+            channel_list.extend(PIXEL_TYPE_FLOAT.to_le_bytes());
+            // linear flag and three reserved bytes, then x and y subsampling
+            channel_list.extend([0; 4]);
+            channel_list.extend(1i32.to_le_bytes());
+            channel_list.extend(1i32.to_le_bytes());
+        }
+        channel_list.push(0);
+
+        let window: Vec<u8> = [0, 0, self.width as i32 - 1, self.height as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+
+        let mut header = Vec::new();
+        let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+            header.extend(name.as_bytes());
+            header.push(0);
+            header.extend(kind.as_bytes());
+            header.push(0);
+            header.extend((value.len() as i32).to_le_bytes());
+            header.extend(value);
+        };
+        attribute("channels", "chlist", &channel_list);
+        attribute("compression", "compression", &[self.compression.id()]);
+        attribute("dataWindow", "box2i", &window);
+        attribute("displayWindow", "box2i", &window);
+        attribute("lineOrder", "lineOrder", &[0]);
+        attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute("screenWindowCenter", "v2f", &[0; 8]);
+        attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+        header
+    }
+
+    /// Rows `y..y + lines`, each holding one channel after another, behind the first row's
+    /// number and the data size.
+    fn chunk(&self, channels: &[&ExrChannel], y: usize, lines: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(4 * channels.len() * self.width * lines);
+        for row in y..y + lines {
+            for channel in channels {
+                let values = &channel.values[row * self.width..(row + 1) * self.width];
+                data.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+            }
+        }
+        if self.compression == ExrCompression::Zip {
+            let compressed = zip_compress(&data);
+            // data that would not shrink is stored as is, which readers tell from its size
+            if compressed.len() < data.len() {
+                data = compressed;
+            }
+        }
+
+        let mut chunk = Vec::with_capacity(8 + data.len());
+        chunk.extend((y as i32).to_le_bytes());
+        chunk.extend((data.len() as i32).to_le_bytes());
+        chunk.extend(data);
+        chunk
+    }
+}
+
+/// OpenEXR's ZIP scheme: the bytes are split into even and odd positions, delta encoded,
+/// then deflated, which lines up the slowly changing high bytes of neighbouring floats.
+fn zip_compress(data: &[u8]) -> Vec<u8> {
+    let mut split: Vec<u8> = data.iter().step_by(2).copied().collect();
+    split.extend(data.iter().skip(1).step_by(2));
+    let mut previous = split.first().copied().unwrap_or(0);
+    for byte in split.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+    zlib_compress(&split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zlib_decompress;
+
+    /// Reads a NUL-terminated string at `*pos`, moving past it.
+    fn string(file: &[u8], pos: &mut usize) -> String {
+        let end = *pos + file[*pos..].iter().position(|&b| b == 0).unwrap();
+        let s = String::from_utf8(file[*pos..end].to_vec()).unwrap();
+        *pos = end + 1;
+        s
+    }
+
+    fn i32_at(file: &[u8], pos: usize) -> i32 {
+        i32::from_le_bytes(file[pos..pos + 4].try_into().unwrap())
+    }
+
+    /// Undoes [`zip_compress`].
+    fn zip_decompress(data: &[u8]) -> Vec<u8> {
+        let mut split = zlib_decompress(data).unwrap();
+        for i in 1..split.len() {
+            split[i] = split[i - 1].wrapping_add(split[i]).wrapping_sub(128);
+        }
+        let (even, odd) = split.split_at(split.len().div_ceil(2));
+        let mut out = Vec::with_capacity(split.len());
+        for i in 0..split.len() {
+            out.push(if i % 2 == 0 { even[i / 2] } else { odd[i / 2] });
+        }
+        out
+    }
+
+    #[test]
+    fn headers_and_offset_tables_describe_the_scanlines() {
+        let (width, height) = (7, 37);
+        let plane =
+            |scale: f32| -> Vec<f32> { (0..width * height).map(|i| i as f32 * scale).collect() };
+        for compression in [ExrCompression::None, ExrCompression::Zip] {
+            let image = ExrImage::new(width, height)
+                .compression(compression)
+                .channel("G", plane(2.))
+                .channel("B", plane(-1.))
+                .channel("albedo.R", plane(0.5))
+                .channel("A", vec![1.; width * height]);
+            let mut file = Vec::new();
+            image.write(&mut file).unwrap();
+            assert_eq!(file[..4], MAGIC);
+            assert_eq!(file[4..8], VERSION);
+
+            let mut pos = 8;
+            let mut attributes = Vec::new();
+            loop {
+                let name = string(&file, &mut pos);
+                if name.is_empty() {
+                    break;
+                }
+                let kind = string(&file, &mut pos);
+                let size = i32_at(&file, pos) as usize;
+                attributes.push((name, kind, file[pos + 4..pos + 4 + size].to_vec()));
+                pos += 4 + size;
+            }
+            let attribute = |name: &str| {
+                attributes
+                    .iter()
+                    .find(|(n, _, _)| n == name)
+                    .map(|(_, _, value)| value.as_slice())
+                    .unwrap()
+            };
+            assert_eq!(attribute("compression"), [compression.id()]);
+            let window: Vec<i32> = (0..4)
+                .map(|i| i32_at(attribute("dataWindow"), 4 * i))
+                .collect();
+            assert_eq!(window, [0, 0, width as i32 - 1, height as i32 - 1]);
+
+            // channels are listed sorted, each name followed by 16 bytes of description
+            let list = attribute("channels");
+            let mut names = Vec::new();
+            let mut at = 0;
+            while list[at] != 0 {
+                names.push(string(list, &mut at));
+                assert_eq!(i32_at(list, at), PIXEL_TYPE_FLOAT);
+                at += 16;
+            }
+            assert_eq!(names, ["A", "B", "G", "albedo.R"]);
+
+            // the offset table points at each block of scanlines in turn, with the last one
+            // ending the file
+            let lines = compression.lines_per_chunk();
+            let blocks = height.div_ceil(lines);
+            let offsets: Vec<usize> = (0..blocks)
+                .map(|i| {
+                    u64::from_le_bytes(file[pos + 8 * i..pos + 8 * i + 8].try_into().unwrap())
+                        as usize
+                })
+                .collect();
+            assert_eq!(offsets[0], pos + 8 * blocks);
+            let mut end = offsets[0];
+            for (i, &offset) in offsets.iter().enumerate() {
+                assert_eq!(offset, end);
+                let y = i * lines;
+                assert_eq!(i32_at(&file, offset), y as i32);
+                let size = i32_at(&file, offset + 4) as usize;
+                let data = &file[offset + 8..offset + 8 + size];
+                end = offset + 8 + size;
+
+                let rows = lines.min(height - y);
+                let raw_size = 4 * names.len() * width * rows;
+                // the smooth test planes always shrink when zipped
+                assert_eq!(size < raw_size, compression == ExrCompression::Zip);
+                let data = if size < raw_size {
+                    zip_decompress(data)
+                } else {
+                    data.to_vec()
+                };
+                let mut expected = Vec::new();
+                for row in y..y + rows {
+                    for name in &names {
+                        let channel = image.channels().iter().find(|c| &c.name == name).unwrap();
+                        let values = &channel.values[row * width..(row + 1) * width];
+                        expected.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+                    }
+                }
+                assert_eq!(data, expected, "{compression:?} block {i}");
+            }
+            assert_eq!(end, file.len());
+        }
+    }
+}
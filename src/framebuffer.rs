@@ -0,0 +1,203 @@
+use crate::{write_hdr, write_pfm, write_png, Color, ExrCompression, ExrImage, PngFormat, Tile};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// How texel coordinates that fall outside an image are brought back inside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    /// Stretches the edge texels outwards.
+    Clamp,
+    /// Repeats the image, flipping every other copy so the seams match.
+    Mirror,
+}
+
+impl WrapMode {
+    fn apply(self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.clamp(0, n - 1),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                if i < n {
+                    i
+                } else {
+                    2 * n - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+impl FromStr for WrapMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repeat" => Ok(WrapMode::Repeat),
+            "clamp" => Ok(WrapMode::Clamp),
+            "mirror" => Ok(WrapMode::Mirror),
+            _ => Err(format!(
+                "unknown wrap mode '{s}', expected repeat, clamp or mirror"
+            )),
+        }
+    }
+}
+
+/// Linear, already-averaged pixel colors stored row by row from the top-left corner.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    #[must_use]
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::black(); width * height],
+        }
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "a framebuffer needs exactly width * height pixels"
+        );
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> &Color {
+        &self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    /// Bilinear lookup at `(u, v)`, which span the image over `[0, 1]` with `v` growing
+    /// downwards.
+    pub fn sample_bilinear(&self, u: f64, v: f64, wrap_u: WrapMode, wrap_v: WrapMode) -> Color {
+        let x = u * self.width as f64 - 0.5;
+        let y = v * self.height as f64 - 0.5;
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let (x, y) = (x.floor() as i64, y.floor() as i64);
+
+        let (x0, x1) = (
+            wrap_u.apply(x, self.width),
+            wrap_u.apply(x.saturating_add(1), self.width),
+        );
+        let (y0, y1) = (
+            wrap_v.apply(y, self.height),
+            wrap_v.apply(y.saturating_add(1), self.height),
+        );
+
+        let lerp = |a: &Color, b: &Color, t: f64| a.clone() * (1. - t) + b.clone() * t;
+        let top = lerp(self.get(x0, y0), self.get(x1, y0), fx);
+        let bottom = lerp(self.get(x0, y1), self.get(x1, y1), fx);
+        lerp(&top, &bottom, fy)
+    }
+
+    /// Copies a tile's pixels, stored row by row, into place.
+    pub fn write_tile(&mut self, tile: &Tile, pixels: &[Color]) {
+        for (row, source) in pixels.chunks(tile.width).enumerate() {
+            let start = (tile.y + row) * self.width + tile.x;
+            self.pixels[start..start + tile.width].clone_from_slice(source);
+        }
+    }
+
+    pub fn write_ppm<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "P3\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in &self.pixels {
+            out.write_all(&pixel.as_output(1))?;
+        }
+        out.flush()
+    }
+
+    pub fn write_png<W: Write>(&self, out: W, format: PngFormat) -> io::Result<()> {
+        write_png(self, out, format)
+    }
+
+    pub fn write_pfm<W: Write>(&self, out: W) -> io::Result<()> {
+        write_pfm(self, out)
+    }
+
+    pub fn write_hdr<W: Write>(&self, out: W) -> io::Result<()> {
+        write_hdr(self, out)
+    }
+
+    pub fn write_exr<W: Write>(&self, out: W, compression: ExrCompression) -> io::Result<()> {
+        ExrImage::from_framebuffer(self)
+            .compression(compression)
+            .write(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_modes_map_every_index_inside() {
+        let n = 4;
+        let indices = [-9, -5, -4, -1, 0, 3, 4, 7, 8, 11];
+        let expected = [
+            (WrapMode::Repeat, [3, 3, 0, 3, 0, 3, 0, 3, 0, 3]),
+            (WrapMode::Clamp, [0, 0, 0, 0, 0, 3, 3, 3, 3, 3]),
+            (WrapMode::Mirror, [0, 3, 3, 0, 0, 3, 3, 0, 0, 3]),
+        ];
+        for (mode, expected) in expected {
+            let mapped: Vec<usize> = indices.iter().map(|&i| mode.apply(i, n)).collect();
+            assert_eq!(mapped, expected, "{mode:?}");
+        }
+        assert_eq!(WrapMode::Mirror.apply(i64::MIN, n), 0);
+        assert_eq!(WrapMode::Repeat.apply(i64::MAX, 1), 0);
+    }
+
+    #[test]
+    fn bilinear_lookups_wrap_outside_the_image() {
+        // a single row of four texels valued 0, 1, 2, 3
+        let pixels = (0..4).map(|i| Color::all(f64::from(i))).collect();
+        let image = Framebuffer::from_pixels(4, 1, pixels);
+        let lookup = |u: f64, wrap| image.sample_bilinear(u, 0.5, wrap, WrapMode::Clamp).r;
+
+        // texel centers sit at (i + 0.5) / 4
+        assert_eq!(lookup(0.375, WrapMode::Repeat), 1.);
+        assert_eq!(lookup(-0.125, WrapMode::Repeat), 3.);
+        assert_eq!(lookup(1.125, WrapMode::Repeat), 0.);
+        assert_eq!(lookup(-0.125, WrapMode::Clamp), 0.);
+        assert_eq!(lookup(1.625, WrapMode::Clamp), 3.);
+        assert_eq!(lookup(-0.125, WrapMode::Mirror), 0.);
+        assert_eq!(lookup(1.375, WrapMode::Mirror), 2.);
+        // halfway between the last texel and the first one across the seam
+        assert_eq!(lookup(1., WrapMode::Repeat), 1.5);
+        assert_eq!(lookup(1., WrapMode::Mirror), 3.);
+    }
+}
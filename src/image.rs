@@ -0,0 +1,611 @@
+use crate::{read_png, Color, Framebuffer, PNG_SIGNATURE};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(err) => write!(f, "{err}"),
+            ImageError::Format(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io(err) => Some(err),
+            ImageError::Format(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(err: io::Error) -> Self {
+        ImageError::Io(err)
+    }
+}
+
+fn format_error<T>(message: impl Into<String>) -> Result<T, ImageError> {
+    Err(ImageError::Format(message.into()))
+}
+
+/// The most pixels a decoded image may have, 16384 by 8192. Headers asking for more are far
+/// more likely corrupt than real, and believing them would exhaust memory.
+pub const MAX_IMAGE_PIXELS: usize = 1 << 27;
+
+/// Checks the dimensions a header claims, returning the pixel count.
+fn pixel_count(width: usize, height: usize) -> Result<usize, ImageError> {
+    if width == 0 || height == 0 {
+        return format_error(format!("image size {width}x{height} has no pixels"));
+    }
+    match width.checked_mul(height) {
+        Some(count) if count <= MAX_IMAGE_PIXELS => Ok(count),
+        _ => format_error(format!("image size {width}x{height} is too large")),
+    }
+}
+
+/// Reads exactly `len` bytes, growing the buffer only as data actually arrives, so a short
+/// file can't make a lying header allocate its claimed size up front.
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>, ImageError> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return format_error("unexpected end of file in pixel data");
+    }
+    Ok(bytes)
+}
+
+/// Decodes an sRGB-encoded value in `[0, 1]` to linear light.
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear value in `[0, 1]` with the sRGB transfer curve, the inverse of
+/// [`srgb_to_linear`].
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.003_130_8 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, ImageError> {
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return format_error("unexpected end of file in header");
+    }
+    Ok(String::from_utf8_lossy(&line).trim_end().to_owned())
+}
+
+fn rgbe_to_color([r, g, b, e]: [u8; 4]) -> Color {
+    if e == 0 {
+        return Color::black();
+    }
+    let scale = 2f64.powi(i32::from(e) - 136);
+    Color::new(
+        (f64::from(r) + 0.5) * scale,
+        (f64::from(g) + 0.5) * scale,
+        (f64::from(b) + 0.5) * scale,
+    )
+}
+
+/// Reads one scanline in any of the three encodings Radiance writers produce.
+fn read_rgbe_scanline<R: Read>(reader: &mut R, width: usize) -> Result<Vec<[u8; 4]>, ImageError> {
+    let mut first = [0u8; 4];
+    reader.read_exact(&mut first)?;
+    let mut scanline = Vec::new();
+
+    let is_adaptive_rle = first[0] == 2 && first[1] == 2 && first[2] & 0x80 == 0;
+    if (8..0x8000).contains(&width) && is_adaptive_rle {
+        let encoded_width = (usize::from(first[2]) << 8) | usize::from(first[3]);
+        if encoded_width != width {
+            return format_error("scanline width does not match the image width");
+        }
+
+        // each channel is run-length encoded separately
+        let mut channels = vec![[0u8; 4]; width];
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let mut count = [0u8; 1];
+                reader.read_exact(&mut count)?;
+                let (run, count) = match count[0] {
+                    c if c > 128 => (true, usize::from(c - 128)),
+                    c => (false, usize::from(c)),
+                };
+                if count == 0 || x + count > width {
+                    return format_error("corrupt run-length encoded scanline");
+                }
+                if run {
+                    let mut value = [0u8; 1];
+                    reader.read_exact(&mut value)?;
+                    for texel in &mut channels[x..x + count] {
+                        texel[channel] = value[0];
+                    }
+                } else {
+                    let mut values = vec![0u8; count];
+                    reader.read_exact(&mut values)?;
+                    for (texel, value) in channels[x..x + count].iter_mut().zip(values) {
+                        texel[channel] = value;
+                    }
+                }
+                x += count;
+            }
+        }
+        return Ok(channels);
+    }
+
+    // flat pixels, possibly with old-style (1, 1, 1, n) repeat markers
+    let mut pixel = first;
+    let mut shift = 0;
+    loop {
+        if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 {
+            let Some(&last) = scanline.last() else {
+                return format_error("repeat marker at the start of a scanline");
+            };
+            // each further marker in a row adds eight higher bits to the count
+            if shift > usize::BITS - 8 {
+                return format_error("corrupt run-length encoded scanline");
+            }
+            let count = usize::from(pixel[3]) << shift;
+            if scanline.len() + count > width {
+                return format_error("corrupt run-length encoded scanline");
+            }
+            scanline.extend(std::iter::repeat_n(last, count));
+            shift += 8;
+        } else {
+            scanline.push(pixel);
+            shift = 0;
+        }
+        if scanline.len() == width {
+            return Ok(scanline);
+        }
+        reader.read_exact(&mut pixel)?;
+    }
+}
+
+/// Decodes a Radiance RGBE (`.hdr`) image into linear colors.
+pub fn read_hdr<R: Read>(reader: R) -> Result<Framebuffer, ImageError> {
+    let mut reader = BufReader::new(reader);
+
+    let magic = read_line(&mut reader)?;
+    if !magic.starts_with("#?") {
+        return format_error("not a Radiance HDR file");
+    }
+    loop {
+        let line = read_line(&mut reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return format_error(format!("unsupported pixel format '{format}'"));
+            }
+        }
+    }
+
+    let resolution = read_line(&mut reader)?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => match (h.parse(), w.parse()) {
+            (Ok(h), Ok(w)) => (h, w),
+            _ => return format_error(format!("bad resolution line '{resolution}'")),
+        },
+        _ => {
+            return format_error(format!(
+                "unsupported resolution line '{resolution}', only -Y h +X w is handled"
+            ))
+        }
+    };
+
+    pixel_count(width, height)?;
+
+    let mut pixels = Vec::new();
+    for _ in 0..height {
+        let scanline = read_rgbe_scanline(&mut reader, width)?;
+        pixels.extend(scanline.into_iter().map(rgbe_to_color));
+    }
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+pub fn load_hdr<P: AsRef<Path>>(path: P) -> Result<Framebuffer, ImageError> {
+    read_hdr(File::open(path)?)
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> Result<Option<u8>, ImageError> {
+    let mut byte = [0u8; 1];
+    Ok((reader.read(&mut byte)? == 1).then_some(byte[0]))
+}
+
+/// Reads the next whitespace-separated word of a PPM or PFM header, skipping `#` comments.
+/// The single whitespace byte ending the word is consumed, so binary data can follow it.
+fn read_token<R: Read>(reader: &mut R) -> Result<String, ImageError> {
+    let mut token = Vec::new();
+    loop {
+        match read_byte(reader)? {
+            None if token.is_empty() => return format_error("unexpected end of file in header"),
+            None => break,
+            Some(b'#') if token.is_empty() => {
+                while !matches!(read_byte(reader)?, None | Some(b'\n')) {}
+            }
+            Some(byte) if byte.is_ascii_whitespace() => {
+                if !token.is_empty() {
+                    break;
+                }
+            }
+            Some(byte) => token.push(byte),
+        }
+    }
+    Ok(String::from_utf8_lossy(&token).into_owned())
+}
+
+fn read_number<R: Read, T: FromStr>(reader: &mut R, what: &str) -> Result<T, ImageError> {
+    let token = read_token(reader)?;
+    match token.parse() {
+        Ok(value) => Ok(value),
+        Err(_) => format_error(format!("expected {what}, found '{token}'")),
+    }
+}
+
+/// Decodes an ASCII (`P3`) or binary (`P6`) PPM image, treating its values as sRGB.
+pub fn read_ppm<R: Read>(reader: R) -> Result<Framebuffer, ImageError> {
+    let mut reader = BufReader::new(reader);
+    let magic = read_token(&mut reader)?;
+    if magic != "P3" && magic != "P6" {
+        return format_error("not a P3 or P6 PPM file");
+    }
+    let width: usize = read_number(&mut reader, "the image width")?;
+    let height: usize = read_number(&mut reader, "the image height")?;
+    let max_value: u32 = read_number(&mut reader, "the maximum value")?;
+    if !(1..=65535).contains(&max_value) {
+        return format_error(format!("maximum value {max_value} is out of range"));
+    }
+
+    let sample_count = 3 * pixel_count(width, height)?;
+
+    let mut samples = Vec::new();
+    if magic == "P3" {
+        for _ in 0..sample_count {
+            samples.push(read_number::<_, u32>(&mut reader, "a pixel value")?);
+        }
+    } else {
+        // two bytes per value, most significant first, once they no longer fit in one
+        let bytes_per_sample = if max_value > 255 { 2 } else { 1 };
+        let bytes = read_bytes(&mut reader, sample_count * bytes_per_sample)?;
+        samples.extend(
+            bytes
+                .chunks_exact(bytes_per_sample)
+                .map(|sample| match sample {
+                    [high, low] => u32::from(*high) << 8 | u32::from(*low),
+                    [value] => u32::from(*value),
+                    _ => unreachable!(),
+                }),
+        );
+    }
+
+    let to_linear =
+        |sample: u32| srgb_to_linear(f64::from(sample.min(max_value)) / f64::from(max_value));
+    let pixels = samples
+        .chunks_exact(3)
+        .map(|rgb| Color::new(to_linear(rgb[0]), to_linear(rgb[1]), to_linear(rgb[2])))
+        .collect();
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+/// Decodes a Portable Float Map, color (`PF`) or grayscale (`Pf`), whose values are already
+/// linear.
+pub fn read_pfm<R: Read>(reader: R) -> Result<Framebuffer, ImageError> {
+    let mut reader = BufReader::new(reader);
+    let channels = match read_token(&mut reader)?.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return format_error("not a PFM file"),
+    };
+    let width: usize = read_number(&mut reader, "the image width")?;
+    let height: usize = read_number(&mut reader, "the image height")?;
+    // the sign of the scale gives the byte order, negative meaning little-endian
+    let scale: f64 = read_number(&mut reader, "the scale")?;
+    if scale == 0. || !scale.is_finite() {
+        return format_error("PFM scale must be a non-zero number");
+    }
+
+    let bytes = read_bytes(&mut reader, 4 * channels * pixel_count(width, height)?)?;
+    let values: Vec<f64> = bytes
+        .chunks_exact(4)
+        .map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            f64::from(if scale < 0. {
+                f32::from_le_bytes(b)
+            } else {
+                f32::from_be_bytes(b)
+            })
+        })
+        .collect();
+
+    // rows run from the bottom of the image to the top
+    let mut image = Framebuffer::new(width, height);
+    for (row, values) in values.chunks_exact(channels * width).enumerate() {
+        for (x, texel) in values.chunks_exact(channels).enumerate() {
+            let color = match texel {
+                [r, g, b] => Color::new(*r, *g, *b),
+                [value] => Color::all(*value),
+                _ => unreachable!(),
+            };
+            image.set(x, height - 1 - row, color);
+        }
+    }
+    Ok(image)
+}
+
+/// Decodes a PPM, PFM, Radiance HDR or PNG image, telling them apart by their first bytes.
+pub fn read_image<R: Read>(reader: R) -> Result<Framebuffer, ImageError> {
+    let mut reader = BufReader::new(reader);
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(b"P3") || magic.starts_with(b"P6") {
+        read_ppm(reader)
+    } else if magic.starts_with(b"PF") || magic.starts_with(b"Pf") {
+        read_pfm(reader)
+    } else if magic.starts_with(b"#?") {
+        read_hdr(reader)
+    } else if magic.starts_with(&PNG_SIGNATURE[..4]) {
+        read_png(reader)
+    } else {
+        format_error("unrecognized image format, expected PPM, PFM, Radiance HDR or PNG")
+    }
+}
+
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<Framebuffer, ImageError> {
+    read_image(File::open(path)?)
+}
+
+/// Writes a little-endian color PFM, keeping the full float range.
+pub fn write_pfm<W: Write>(image: &Framebuffer, mut out: W) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    for row in image.pixels().chunks(image.width().max(1)).rev() {
+        for pixel in row {
+            for value in [pixel.r, pixel.g, pixel.b] {
+                out.write_all(&(value as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
+
+fn color_to_rgbe(color: &Color) -> [u8; 4] {
+    let max = color.r.max(color.g).max(color.b);
+    if max < 1e-32 || !max.is_finite() {
+        return [0; 4];
+    }
+    // the shared exponent puts the largest channel's mantissa in [128, 256)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256. / 2f64.powi(exponent);
+    let channel = |c: f64| (c.max(0.) * scale).min(255.) as u8;
+    [
+        channel(color.r),
+        channel(color.g),
+        channel(color.b),
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+/// Run-length encodes one channel of a scanline, the way [`read_rgbe_scanline`] expects.
+fn write_rle_channel(out: &mut Vec<u8>, bytes: &[u8]) {
+    let mut x = 0;
+    while x < bytes.len() {
+        // find where the next run worth encoding starts
+        let (mut start, mut run) = (x, 0);
+        while start < bytes.len() {
+            run = bytes[start..]
+                .iter()
+                .take(127)
+                .take_while(|&&byte| byte == bytes[start])
+                .count();
+            if run >= 4 {
+                break;
+            }
+            start += run;
+        }
+
+        for literal in bytes[x..start].chunks(128) {
+            out.push(literal.len() as u8);
+            out.extend_from_slice(literal);
+        }
+        if start < bytes.len() {
+            out.extend([128 + run as u8, bytes[start]]);
+            start += run;
+        }
+        x = start;
+    }
+}
+
+/// Writes a run-length encoded Radiance RGBE image.
+pub fn write_hdr<W: Write>(image: &Framebuffer, mut out: W) -> io::Result<()> {
+    let width = image.width();
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height(),
+        width
+    )?;
+    let mut scanline = Vec::with_capacity(4 * width + 4);
+    for row in image.pixels().chunks(width.max(1)) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(color_to_rgbe).collect();
+        scanline.clear();
+        if (8..0x8000).contains(&width) {
+            scanline.extend([2, 2, (width >> 8) as u8, width as u8]);
+            for channel in 0..4 {
+                let bytes: Vec<u8> = rgbe.iter().map(|texel| texel[channel]).collect();
+                write_rle_channel(&mut scanline, &bytes);
+            }
+        } else {
+            // too narrow or too wide for run-length encoding
+            scanline.extend(rgbe.iter().flatten());
+        }
+        out.write_all(&scanline)?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn error(result: Result<Framebuffer, ImageError>) -> String {
+        result.expect_err("image should be rejected").to_string()
+    }
+
+    #[test]
+    fn headers_cannot_demand_huge_allocations() {
+        assert_eq!(
+            error(read_ppm(&b"P6\n100000000 100000000\n255\n"[..])),
+            "image size 100000000x100000000 is too large"
+        );
+        assert_eq!(
+            error(read_pfm(&b"PF\n4000000000 4000000000\n-1.0\n"[..])),
+            "image size 4000000000x4000000000 is too large"
+        );
+        // allowed by the cap, but the data isn't there
+        assert_eq!(
+            error(read_ppm(&b"P6\n8192 8192\n255\n\x01\x02\x03"[..])),
+            "unexpected end of file in pixel data"
+        );
+        assert_eq!(
+            error(read_pfm(&b"Pf\n8192 8192\n1.0\n\0\0\0\0"[..])),
+            "unexpected end of file in pixel data"
+        );
+        assert_eq!(
+            error(read_ppm(&b"P3\n4096 4096\n255\n1 2 3\n"[..])),
+            "unexpected end of file in header"
+        );
+    }
+
+    #[test]
+    fn hdr_resolutions_are_checked() {
+        let header = |resolution: &str| {
+            format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{resolution}\n").into_bytes()
+        };
+        assert_eq!(
+            error(read_hdr(&header("-Y 3000000000 +X 3000000000")[..])),
+            "image size 3000000000x3000000000 is too large"
+        );
+        assert_eq!(
+            error(read_hdr(&header("-Y 0 +X 16")[..])),
+            "image size 16x0 has no pixels"
+        );
+        // repeat markers with a count of zero, enough to shift the count past its width
+        let mut markers = header("-Y 1 +X 4");
+        markers.extend([9, 9, 9, 128]);
+        for _ in 0..12 {
+            markers.extend([1, 1, 1, 0]);
+        }
+        assert_eq!(
+            error(read_hdr(&markers[..])),
+            "corrupt run-length encoded scanline"
+        );
+
+        let mut truncated = header("-Y 8192 +X 16384");
+        truncated.extend([2, 2, 0x40, 0]);
+        assert!(read_hdr(&truncated[..]).is_err());
+    }
+
+    #[test]
+    fn empty_images_are_rejected() {
+        assert_eq!(
+            error(read_ppm(&b"P6\n0 5\n255\n"[..])),
+            "image size 0x5 has no pixels"
+        );
+        assert_eq!(
+            error(read_pfm(&b"PF\n3 0\n-1.0\n"[..])),
+            "image size 3x0 has no pixels"
+        );
+    }
+
+    fn assert_same_pixels(image: &Framebuffer, expected: &Framebuffer) {
+        assert_eq!(
+            (image.width(), image.height()),
+            (expected.width(), expected.height())
+        );
+        for (i, (a, b)) in image.pixels().iter().zip(expected.pixels()).enumerate() {
+            assert_eq!((a.r, a.g, a.b), (b.r, b.g, b.b), "pixel {i}");
+        }
+    }
+
+    #[test]
+    fn pfm_round_trips_exactly() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let pixels = (0..5 * 3)
+            .map(|_| {
+                let value = |rng: &mut StdRng| f64::from(rng.gen_range(-1e4f32..1e4));
+                Color::new(value(&mut rng), value(&mut rng), value(&mut rng))
+            })
+            .collect();
+        let image = Framebuffer::from_pixels(5, 3, pixels);
+        let mut file = Vec::new();
+        write_pfm(&image, &mut file).unwrap();
+        assert_same_pixels(&read_pfm(file.as_slice()).unwrap(), &image);
+    }
+
+    #[test]
+    fn hdr_round_trips_to_rgbe_precision() {
+        let mut rng = StdRng::seed_from_u64(2);
+        // narrow rows are stored flat, wide ones run-length encoded with runs longer than
+        // the 127 a single run can hold
+        for width in [5, 300, 0x8000] {
+            let pixels = (0..width * 3)
+                .map(|i| match (i % width) / 100 % 2 {
+                    0 => Color::new(0.5, 2., 1e-3),
+                    _ => Color::new(rng.gen_range(0. ..8.), rng.gen(), rng.gen_range(0. ..1e3)),
+                })
+                .collect();
+            let image = Framebuffer::from_pixels(width, 3, pixels);
+            let mut file = Vec::new();
+            write_hdr(&image, &mut file).unwrap();
+            let decoded = read_hdr(file.as_slice()).unwrap();
+
+            let quantized: Vec<Color> = image
+                .pixels()
+                .iter()
+                .map(|color| rgbe_to_color(color_to_rgbe(color)))
+                .collect();
+            assert_same_pixels(&decoded, &Framebuffer::from_pixels(width, 3, quantized));
+            for (a, b) in decoded.pixels().iter().zip(image.pixels()) {
+                let max = b.r.max(b.g).max(b.b);
+                for (a, b) in [(a.r, b.r), (a.g, b.g), (a.b, b.b)] {
+                    assert!((a - b).abs() <= max / 128., "{a} != {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rle_shrinks_flat_scanlines() {
+        let image = Framebuffer::from_pixels(1000, 2, vec![Color::new(0.1, 0.2, 0.3); 2000]);
+        let mut file = Vec::new();
+        write_hdr(&image, &mut file).unwrap();
+        assert!(file.len() < 200, "{} bytes", file.len());
+        assert_same_pixels(
+            &read_hdr(file.as_slice()).unwrap(),
+            &Framebuffer::from_pixels(
+                1000,
+                2,
+                vec![rgbe_to_color(color_to_rgbe(&Color::new(0.1, 0.2, 0.3))); 2000],
+            ),
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{power_heuristic, rand, Collision, Color, Point, Ray, Scene, Vec3};
+use std::str::FromStr;
+
+/// How the radiance arriving along a camera ray is estimated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Integrator {
+    /// Follows scattered rays only, so light is found when a bounce happens to hit it.
+    Naive,
+    /// Also aims a shadow ray at one light per diffuse bounce, combining both strategies with
+    /// multiple importance sampling.
+    #[default]
+    NextEvent,
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "naive" => Ok(Integrator::Naive),
+            "nee" => Ok(Integrator::NextEvent),
+            _ => Err(format!("unknown integrator '{s}', expected naive or nee")),
+        }
+    }
+}
+
+impl Integrator {
+    /// The radiance arriving along a camera ray, along with the surface the ray hit first.
+    pub fn radiance(self, ray: &Ray, scene: &Scene, max_depth: i32) -> (Color, Option<Collision>) {
+        let hit = scene.collide(ray, 0.001, f64::INFINITY);
+        let radiance = match self {
+            Integrator::Naive => ray.shade(hit.as_ref(), scene, max_depth),
+            Integrator::NextEvent => shade(ray, hit.as_ref(), scene, max_depth, None),
+        };
+        (radiance, hit)
+    }
+}
+
+/// `bsdf_pdf` is the density the previous bounce picked `ray` with, `None` for camera rays and
+/// mirror-like bounces, whose light was not sampled and so keeps its full weight.
+fn next_event(ray: &Ray, scene: &Scene, depth: i32, bsdf_pdf: Option<f64>) -> Color {
+    if depth <= 0 {
+        return Color::black();
+    }
+    shade(
+        ray,
+        scene.collide(ray, 0.001, f64::INFINITY).as_ref(),
+        scene,
+        depth,
+        bsdf_pdf,
+    )
+}
+
+/// The rest of [`next_event`], once `ray` is known to hit `hit` first.
+fn shade(
+    ray: &Ray,
+    hit: Option<&Collision>,
+    scene: &Scene,
+    depth: i32,
+    bsdf_pdf: Option<f64>,
+) -> Color {
+    if depth <= 0 {
+        return Color::black();
+    }
+
+    let Some(collision) = hit else {
+        let radiance = scene.background.color(ray.direction);
+        return match bsdf_pdf {
+            Some(pdf) if scene.background.is_light() => {
+                radiance * power_heuristic(pdf, background_pdf(scene, ray.direction))
+            }
+            _ => radiance,
+        };
+    };
+
+    let mut color = collision.material.emitted(collision);
+    if let Some(pdf) = bsdf_pdf {
+        if color.luminance() > 0. {
+            color = color * power_heuristic(pdf, emitter_pdf(scene, ray.origin, ray.direction));
+        }
+    }
+
+    let wo = -ray.direction.unit();
+    let Some(sample) = collision.material.sample(wo, collision) else {
+        return color;
+    };
+
+    let scattered = Ray::new(collision.point, sample.direction);
+    if sample.delta {
+        color + sample.weight * next_event(&scattered, scene, depth - 1, None)
+    } else {
+        color = color + direct_light(scene, wo, collision);
+        color + sample.weight * next_event(&scattered, scene, depth - 1, Some(sample.pdf))
+    }
+}
+
+/// Density of the emitter strategy picking `direction`: each emitter is chosen with equal
+/// probability, and several may lie along the same direction.
+fn emitter_pdf(scene: &Scene, origin: Point, direction: Vec3) -> f64 {
+    let total: f64 = scene
+        .lights
+        .iter()
+        .map(|light| light.pdf(origin, direction))
+        .sum();
+    total / scene.light_count() as f64
+}
+
+fn background_pdf(scene: &Scene, direction: Vec3) -> f64 {
+    scene.background.pdf(direction) / scene.light_count() as f64
+}
+
+/// Light from one randomly chosen light reflected at `collision` toward `wo`.
+fn direct_light(scene: &Scene, wo: Vec3, collision: &Collision) -> Color {
+    let count = scene.light_count();
+    if count == 0 {
+        return Color::black();
+    }
+    let origin = collision.point;
+    let index = ((rand() * count as f64) as usize).min(count - 1);
+
+    let (direction, radiance, light_pdf) = match scene.lights.get(index) {
+        Some(light) => {
+            let Some(sample) = light.sample(origin) else {
+                return Color::black();
+            };
+            // whichever emitter the shadow ray reaches first is the one that is seen
+            let shadow = Ray::new(origin, sample.direction);
+            let Some(hit) = scene.collide(&shadow, 0.001, f64::INFINITY) else {
+                return Color::black();
+            };
+            let radiance = hit.material.emitted(&hit);
+            let pdf = emitter_pdf(scene, origin, sample.direction);
+            (sample.direction, radiance, pdf)
+        }
+        None => {
+            let Some(sample) = scene.background.sample() else {
+                return Color::black();
+            };
+            let shadow = Ray::new(origin, sample.direction);
+            if scene.collide(&shadow, 0.001, f64::INFINITY).is_some() {
+                return Color::black();
+            }
+            (sample.direction, sample.radiance, sample.pdf / count as f64)
+        }
+    };
+
+    let wi = direction.unit();
+    let bsdf_pdf = collision.material.pdf(wi, wo, collision);
+    if light_pdf <= 0. || bsdf_pdf <= 0. {
+        return Color::black();
+    }
+    collision.material.eval(wi, wo, collision)
+        * radiance
+        * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        seed_rng, Camera, CollidableVec, DiffuseLight, Lambertian, LatLongMap, RenderSettings,
+        Renderer, SolidColor, Sphere, Triangle,
+    };
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    /// A grey floor under a small lamp, beneath a dim sky with one bright patch.
+    fn lamp_over_floor() -> Scene {
+        let floor = Lambertian::new_arc(Color::all(0.7));
+        let lamp = DiffuseLight::new_arc(Color::new(30., 25., 20.));
+        let corners =
+            [(-5., -5.), (-5., 5.), (5., 5.), (5., -5.)].map(|(x, z)| Point::new(x, 0., z));
+        let objects: CollidableVec = vec![
+            Triangle::boxed(corners[0], corners[1], corners[2], floor.clone()),
+            Triangle::boxed(corners[0], corners[2], corners[3], floor),
+            Sphere::boxed(Point::new(0.5, 1.5, 0.), 0.3, lamp),
+        ];
+
+        let (width, height) = (16, 8);
+        let texels = (0..width * height)
+            .map(|i| match (i % width, i / width) {
+                (3, 1) => Color::new(20., 20., 30.),
+                _ => Color::all(0.2),
+            })
+            .collect();
+        let camera = Camera::new()
+            .look_from(Point::new(0., 3., 4.))
+            .look_at(Point::origin())
+            .vfov(50.)
+            .lens_radius(0.);
+        Scene::new(objects, camera).background(Arc::new(LatLongMap::new(width, height, texels)))
+    }
+
+    fn mean_luminance(scene: &Scene, integrator: Integrator) -> f64 {
+        let settings = RenderSettings::new()
+            .width(24)
+            .height(24)
+            .samples(256)
+            .max_depth(5)
+            .integrator(integrator)
+            .threads(4)
+            .seed(3);
+        let image = Renderer::new(settings).render(scene);
+        let total: f64 = image.pixels().iter().map(Color::luminance).sum();
+        total / image.pixels().len() as f64
+    }
+
+    #[test]
+    fn next_event_estimation_agrees_with_plain_path_tracing() {
+        let scene = lamp_over_floor();
+        assert_eq!(scene.light_count(), 2);
+        let naive = mean_luminance(&scene, Integrator::Naive);
+        let next_event = mean_luminance(&scene, Integrator::NextEvent);
+        assert!(
+            (naive - next_event).abs() < 0.03 * naive,
+            "naive {naive}, next event {next_event}"
+        );
+    }
+
+    /// Solid angle of a triangle seen from the origin (Van Oosterom and Strackee).
+    fn triangle_solid_angle(a: Vec3, b: Vec3, c: Vec3) -> f64 {
+        let (la, lb, lc) = (a.len(), b.len(), c.len());
+        let numerator = a.dot_product(b.cross(c)).abs();
+        let denominator =
+            la * lb * lc + a.dot_product(b) * lc + a.dot_product(c) * lb + b.dot_product(c) * la;
+        2. * numerator.atan2(denominator)
+    }
+
+    #[test]
+    fn emitter_pdf_matches_how_lights_are_sampled() {
+        let lamp = DiffuseLight::new_arc(Color::white());
+        let (a, b, c) = (
+            Point::new(3., 1., 0.),
+            Point::new(3., 1., 1.),
+            Point::new(3., 2., 0.),
+        );
+        let objects: CollidableVec = vec![
+            Sphere::boxed(Point::new(0., 2., 0.), 0.5, lamp.clone()),
+            Triangle::boxed(a, b, c, lamp),
+        ];
+        let scene =
+            Scene::new(objects, Camera::new()).background(SolidColor::new_arc(Color::black()));
+        assert_eq!(scene.light_count(), 2);
+
+        // each light is picked equally often, so the mean of 1 / pdf over the directions
+        // picked is the solid angle the lights cover together
+        seed_rng(5);
+        let origin = Point::origin();
+        let samples = 200_000;
+        let mut sum = 0.;
+        for i in 0..samples {
+            let sample = scene.lights[i % 2].sample(origin).unwrap();
+            sum += 1. / emitter_pdf(&scene, origin, sample.direction);
+        }
+        let cos_max = (1. - 0.25 / 4f64).sqrt();
+        let expected = 2. * PI * (1. - cos_max) + triangle_solid_angle(a, b, c);
+        let estimate = sum / samples as f64;
+        assert!(
+            (estimate - expected).abs() < 0.01 * expected,
+            "{estimate} != {expected}"
+        );
+
+        assert_eq!(emitter_pdf(&scene, origin, Vec3::new(0., -1., 0.)), 0.);
+    }
+}
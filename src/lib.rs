@@ -0,0 +1,63 @@
+mod aabb;
+mod aov;
+mod background;
+mod bvh;
+mod camera;
+mod collidable;
+mod color;
+mod denoise;
+mod distribution;
+mod exr;
+mod framebuffer;
+mod image;
+mod integrator;
+mod light;
+mod material;
+mod microfacet;
+mod noise;
+mod obj;
+mod png;
+mod point;
+mod principled;
+mod ray;
+mod renderer;
+mod scene;
+mod scene_file;
+mod texture;
+mod tiles;
+mod tonemap;
+mod triangle;
+mod utility;
+mod zlib;
+
+pub use aabb::*;
+pub use aov::*;
+pub use background::*;
+pub use bvh::*;
+pub use camera::*;
+pub use collidable::*;
+pub use color::*;
+pub use denoise::*;
+pub use distribution::*;
+pub use exr::*;
+pub use framebuffer::*;
+pub use image::*;
+pub use integrator::*;
+pub use light::*;
+pub use material::*;
+pub use microfacet::*;
+pub use noise::*;
+pub use obj::*;
+pub use png::*;
+pub use point::*;
+pub use principled::*;
+pub use ray::*;
+pub use renderer::*;
+pub use scene::*;
+pub use scene_file::*;
+pub use texture::*;
+pub use tiles::*;
+pub use tonemap::*;
+pub use triangle::*;
+pub use utility::*;
+pub use zlib::*;
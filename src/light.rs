@@ -0,0 +1,51 @@
+use crate::{rand, Point, Vec3};
+use std::f64::consts::PI;
+
+pub struct LightSample {
+    /// Points from the shading point at the emitter; not normalized.
+    pub direction: Vec3,
+    pub pdf: f64,
+}
+
+/// Geometry that can be aimed at directly, used for shadow rays toward emissive surfaces.
+pub trait Emitter: Send + Sync {
+    /// Picks a direction from `origin` toward a point on the emitter.
+    fn sample(&self, origin: Point) -> Option<LightSample>;
+
+    /// Solid-angle density with which [`Emitter::sample`] returns `direction` from `origin`.
+    fn pdf(&self, origin: Point, direction: Vec3) -> f64;
+}
+
+/// Two unit vectors completing `w` (a unit vector) to an orthonormal basis.
+pub fn orthonormal_basis(w: Vec3) -> (Vec3, Vec3) {
+    let a = if w.x.abs() > 0.9 {
+        Vec3::new(0., 1., 0.)
+    } else {
+        Vec3::new(1., 0., 0.)
+    };
+    let v = w.cross(a).unit();
+    (w.cross(v), v)
+}
+
+/// Uniform direction inside the cone around unit `axis` whose half-angle has cosine `cos_max`.
+pub fn sample_cone(axis: Vec3, cos_max: f64) -> Vec3 {
+    let z = 1. + rand() * (cos_max - 1.);
+    let phi = 2. * PI * rand();
+    let r = (1. - z * z).max(0.).sqrt();
+    let (u, v) = orthonormal_basis(axis);
+    r * phi.cos() * u + r * phi.sin() * v + z * axis
+}
+
+pub fn cone_pdf(cos_max: f64) -> f64 {
+    1. / (2. * PI * (1. - cos_max))
+}
+
+/// Balances two sampling strategies, favouring whichever was more likely to pick the direction.
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0. {
+        a / (a + b)
+    } else {
+        0.
+    }
+}
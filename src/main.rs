@@ -1,57 +1,20 @@
-mod camera;
-mod collidable;
-mod color;
-mod material;
-mod point;
-mod ray;
-mod utility;
-
-pub use camera::*;
-pub use collidable::*;
-pub use color::*;
-pub use material::*;
-pub use point::*;
-pub use ray::*;
-pub use utility::*;
-
-use once_cell::sync::Lazy;
+mod cli;
+
+use cli::{parse_args, Command, Options, USAGE};
+use ray_tracer::*;
 use std::{
     fs::{self, OpenOptions},
-    io::{Read, Write},
-    sync::mpsc,
-    sync::{Arc, RwLock},
-    thread,
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicI32, Ordering},
+    sync::Arc,
 };
 
-
-static WORLD: Lazy<Arc<RwLock<CollidableVec>>> =
-    Lazy::new(|| Arc::new(RwLock::new(CollidableVec::new())));
-
-pub const ASPECT_RATIO: f64 = 1.;
-pub const IMG_W: i32 = 3840;
-pub const IMG_H: i32 = (IMG_W as f64 / ASPECT_RATIO) as i32;
-
-pub const SAMPLES: u32 = 256;
-pub const MAX_DEPTH: i32 = 128;
-pub const THREAD_INTERVAL: i32 = 240;
-pub const NUM_THREADS: i32 = IMG_W / THREAD_INTERVAL;
-
-fn main() {
-    assert!(
-        IMG_W % THREAD_INTERVAL == 0,
-        "image width must be divisible by the thread interval"
-    );
-    assert!(
-        IMG_H % THREAD_INTERVAL == 0,
-        "image height must be divisible by the thread interval"
-    );
-
+fn random_scene() -> Scene {
+    let mut objects = CollidableVec::new();
     let material_ground = Lambertian::new_arc(Color::new(0.5, 0.5, 0.5));
-    let material_solid = Lambertian::new_arc(Color::new(0.7, 0.3, 0.3));
-    let material_metal = Metal::new_arc(Color::new(0.6, 0.6, 0.6), 0.15);
-    let material_dielectric = Dielectric::new_arc(1.5);
 
-    add_to_world(Sphere::boxed(
+    objects.push(Sphere::boxed(
         Point::new(0.0, -1000., -1.0),
         1000.,
         material_ground.clone(),
@@ -67,12 +30,12 @@ fn main() {
                 if random < 0.8 {
                     let albedo = Color::random() * Color::random();
                     material = Lambertian::new_arc(albedo);
-                    add_to_world(Sphere::boxed(center, 0.2, material));
+                    objects.push(Sphere::boxed(center, 0.2, material));
                 } else {
                     let albedo = Color::rand_range(0.5, 1.);
                     let fuzz = rand_range(0., 0.35);
                     material = Metal::new_arc(albedo, fuzz);
-                    add_to_world(Sphere::boxed(center, 0.2, material));
+                    objects.push(Sphere::boxed(center, 0.2, material));
                 }
             }
         }
@@ -84,50 +47,199 @@ fn main() {
         .look_at(Point::all(0.))
         .lens_radius(0.02);
 
-    let mut data = vec![vec![Vec::<u8>::new(); THREAD_INTERVAL as usize]; NUM_THREADS as usize];
-    let (sender, reciever) = mpsc::channel();
-
-    for thread_num in 1..=NUM_THREADS {
-        let sender = sender.clone();
-        let camera = camera.clone();
-        thread::spawn(move || {
-            for i in (THREAD_INTERVAL * (thread_num - 1))..(THREAD_INTERVAL * thread_num) {
-                for j in 0..IMG_W {
-                    let mut color = Color::black();
-                    for _ in 0..SAMPLES {
-                        let x = (j as f64 + rand()) / (IMG_W - 1) as f64;
-                        let y = (i as f64 + rand()) / (IMG_H - 1) as f64;
-                        let ray = camera.get_ray(x, y);
-                        color = color + ray.color();
-                    }
-                    sender.send((color.as_output(SAMPLES), thread_num)).unwrap();
-                }
-            }
-        });
+    Scene::new(objects, camera)
+}
+
+fn settings_for(options: &Options, scene_settings: SceneSettings) -> RenderSettings {
+    let defaults = RenderSettings::default();
+    let mut settings = RenderSettings::new()
+        .width(
+            options
+                .width
+                .unwrap_or(scene_settings.width.map_or(defaults.width, |w| w as usize)),
+        )
+        .height(
+            options.height.unwrap_or(
+                scene_settings
+                    .height
+                    .map_or(defaults.height, |h| h as usize),
+            ),
+        )
+        .samples(
+            options
+                .spp
+                .or(scene_settings.samples)
+                .unwrap_or(defaults.samples),
+        )
+        .max_depth(
+            options
+                .depth
+                .or(scene_settings.max_depth)
+                .unwrap_or(defaults.max_depth),
+        )
+        .threads(options.threads.unwrap_or(defaults.threads));
+    if let Some(threshold) = options.adaptive {
+        settings = settings.adaptive_threshold(threshold);
+    }
+    if let Some(min_samples) = options.min_spp {
+        settings = settings.min_samples(min_samples);
+    }
+    if let Some(tile_size) = options.tile_size {
+        settings = settings.tile_size(tile_size);
+    }
+    if let Some(tile_order) = options.tile_order {
+        settings = settings.tile_order(tile_order);
+    }
+    if let Some(integrator) = options.integrator {
+        settings = settings.integrator(integrator);
+    }
+    if let Some(seed) = options.seed {
+        settings = settings.seed(seed);
     }
-    drop(sender);
+    settings.aovs(!options.aovs.is_empty() || options.denoise.is_some())
+}
 
-    let mut counter = 0;
-    let mut last_percent = -1.;
-    while let Ok((bytes, thread_num)) = reciever.recv() {
-        let percent = ((counter as f64 / (IMG_H * IMG_W) as f64) * 100.).floor();
-        if percent != last_percent {
-            println!("Progress: {percent}%");
-            last_percent = percent;
+fn tone_mapping(options: &Options) -> ToneMapping {
+    let operator = match (options.tonemap.unwrap_or_default(), options.white_point) {
+        (ToneMapper::ExtendedReinhard { .. }, Some(white)) => {
+            ToneMapper::ExtendedReinhard { white }
+        }
+        (operator, _) => operator,
+    };
+    let mut tone_mapping = ToneMapping::new()
+        .exposure(options.exposure.unwrap_or(0.))
+        .operator(operator);
+    if let Some(white) = options.white_balance.clone() {
+        tone_mapping = tone_mapping.white_balance(white);
+    }
+    tone_mapping
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Render(options)) => *options,
+        Ok(Command::Help) => {
+            print!("{USAGE}");
+            return;
         }
+        Err(err) => {
+            eprintln!("render: {err}\ntry 'render --help' for more information");
+            std::process::exit(2);
+        }
+    };
 
-        data[(thread_num - 1) as usize].push(bytes);
-        counter += 1;
+    if let Some(seed) = options.seed {
+        seed_rng(seed);
+    }
+    let (scene, scene_settings) = match &options.scene {
+        Some(path) => {
+            let description = load_scene(path).unwrap_or_else(|err| {
+                eprintln!("{}: {err}", path.display());
+                std::process::exit(1);
+            });
+            (description.scene, description.settings)
+        }
+        None => (random_scene(), SceneSettings::default()),
+    };
+    let settings = settings_for(&options, scene_settings);
+
+    let last_percent = AtomicI32::new(-1);
+    let renderer = Renderer::new(settings).on_progress(move |done| {
+        let percent = (done * 100.).floor() as i32;
+        if last_percent.fetch_max(percent, Ordering::Relaxed) < percent {
+            println!("Progress: {percent}%");
+        }
+    });
+    let mut output = renderer.render_output(&scene);
+    if let (Some(strength), Some(aovs)) = (options.denoise, &output.aovs) {
+        println!("Denoising.");
+        output.image = Denoiser::new()
+            .strength(strength)
+            .threads(settings.threads)
+            .denoise(&output.image, &Guides::from_aovs(aovs));
     }
 
     println!("Progress: 100%, writing to file.");
+    let filename = options.out.clone().unwrap_or_else(next_output_path);
+    write_output(&output, &filename, &options);
+    println!("wrote to {}, exiting.", filename.display())
+}
+
+/// Writes one image in the format its extension names, exiting on failure.
+fn write_image(image: &Framebuffer, path: &Path, options: &Options) {
+    let written = fs::File::create(path).and_then(|file| {
+        let out = BufWriter::new(file);
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("pfm") => image.write_pfm(out),
+            Some("hdr") => image.write_hdr(out),
+            Some("exr") => image.write_exr(out, options.exr_compression.unwrap_or_default()),
+            Some("png") => image.write_png(out, options.png_format.unwrap_or_default()),
+            _ => image.write_ppm(out),
+        }
+    });
+    if let Err(err) = written {
+        eprintln!("{}: {err}", path.display());
+        std::process::exit(1);
+    }
+}
+
+/// Writes the beauty image and the requested AOVs: as extra channels of an EXR, or else as
+/// images of their own named like `trace-1.depth.png`.
+fn write_output(output: &RenderOutput, path: &Path, options: &Options) {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("ppm");
+
+    if extension == "exr" {
+        let mut image = ExrImage::from_framebuffer(&output.image)
+            .compression(options.exr_compression.unwrap_or_default());
+        if let Some(passes) = &output.aovs {
+            for &aov in &options.aovs {
+                image = passes.add_to_exr(image, aov);
+            }
+        }
+        let written = fs::File::create(path).and_then(|file| image.write(BufWriter::new(file)));
+        if let Err(err) = written {
+            eprintln!("{}: {err}", path.display());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // the float formats keep the render's linear values untouched
+    let is_float = matches!(extension, "pfm" | "hdr");
+    if is_float {
+        write_image(&output.image, path, options);
+    } else {
+        write_image(&tone_mapping(options).apply(&output.image), path, options);
+    }
+    if let Some(passes) = &output.aovs {
+        for &aov in &options.aovs {
+            let image = if is_float {
+                passes.raw(aov)
+            } else {
+                passes.visualize(aov)
+            };
+            write_image(
+                &image,
+                &path.with_extension(format!("{}.{extension}", aov.name())),
+                options,
+            );
+        }
+    }
+}
+
+fn next_output_path() -> PathBuf {
     let mut buf: String;
     {
-        let mut n = fs::File::open("rayout/.n.txt").unwrap_or_else(|_|{
+        let mut n = fs::File::open("rayout/.n.txt").unwrap_or_else(|_| {
             fs::create_dir("rayout").ok();
-            fs::File::create("rayout/.n.txt").unwrap().write_all(b"0").unwrap();
+            fs::File::create("rayout/.n.txt")
+                .unwrap()
+                .write_all(b"0")
+                .unwrap();
             fs::File::open("rayout/.n.txt").unwrap()
-
         });
 
         buf = String::new();
@@ -146,15 +258,5 @@ fn main() {
         )
         .unwrap();
     }
-    let filename = format!("rayout/trace-{}.ppm", buf.trim());
-    let mut file = fs::File::create(filename.clone()).unwrap();
-    file.write_all(format!("P3\n{IMG_W} {IMG_H}\n255\n").as_bytes())
-        .unwrap();
-
-    for thread_vec in data.iter().rev() {
-        for bytes in thread_vec.iter().rev() {
-            file.write_all(&bytes).unwrap();
-        }
-    }
-    println!("wrote to {filename}, exiting.")
+    PathBuf::from(format!("rayout/trace-{}.ppm", buf.trim()))
 }
@@ -1,83 +1,132 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
-use crate::{rand, Collision, Color, Facing, Ray, Vec3};
+use crate::{orthonormal_basis, rand, Collision, Color, Facing, Texture, Vec3};
 
-#[derive(Default)]
-pub enum ScatterOutcome {
-    Scattered,
-
-    #[default]
-    Absorbed,
-}
-
-#[derive(Default)]
-pub struct ScatterResult {
-    pub outcome: ScatterOutcome,
-    pub attenuation: Color,
-    pub scattered_ray: Ray,
+/// A direction picked by [`Material::sample`].
+pub struct BsdfSample {
+    /// Unit direction the light arrives from, pointing away from the surface.
+    pub direction: Vec3,
+    /// Solid-angle density of `direction`, or for delta lobes the probability of having
+    /// chosen this lobe.
+    pub pdf: f64,
+    /// What light arriving from `direction` is multiplied by on its way to the viewer,
+    /// already divided by `pdf`.
+    pub weight: Color,
+    /// Set for mirror-like lobes that scatter into a single direction, which no other
+    /// strategy can find; `eval` and `pdf` are zero for them.
+    pub delta: bool,
 }
 
+/// Directions follow the surface convention: `wo` points back toward the viewer, `wi` toward
+/// where the light comes from, both away from the surface.
 pub trait Material {
-    fn scatter(&self, ray_in: &Ray, collision: &Collision) -> ScatterResult;
+    /// The BSDF for light arriving along `wi` and leaving along `wo`, times the cosine of `wi`
+    /// with the shading normal.
+    fn eval(&self, _wi: Vec3, _wo: Vec3, _collision: &Collision) -> Color {
+        Color::black()
+    }
+
+    /// Picks an incoming direction for light leaving along `wo`; `None` when the material
+    /// absorbs the path.
+    fn sample(&self, wo: Vec3, collision: &Collision) -> Option<BsdfSample>;
+
+    /// Solid-angle density with which [`Material::sample`] returns `wi`.
+    fn pdf(&self, _wi: Vec3, _wo: Vec3, _collision: &Collision) -> f64 {
+        0.
+    }
+
+    fn emitted(&self, _collision: &Collision) -> Color {
+        Color::black()
+    }
+
+    /// The overall color the surface reflects, as the albedo pass records it; clear and
+    /// emissive materials count as white.
+    fn albedo(&self, _collision: &Collision) -> Color {
+        Color::white()
+    }
+
+    /// Whether [`Material::emitted`] can be non-black, making surfaces with it worth sampling
+    /// as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 impl Lambertian {
-    pub fn new(albedo: Color) -> Self {
-        Self { albedo }
+    pub fn new(albedo: impl Texture + 'static) -> Self {
+        Self {
+            albedo: Arc::new(albedo),
+        }
     }
-    pub fn new_arc(albedo: Color) -> Arc<Self> {
+    pub fn new_arc(albedo: impl Texture + 'static) -> Arc<Self> {
         Arc::new(Self::new(albedo))
     }
 }
 impl Material for Lambertian {
-    fn scatter(&self, _: &Ray, collision: &Collision) -> ScatterResult {
-        let mut result = ScatterResult::default();
-        let mut direction = collision.normal + Vec3::random_unit_vector();
-        if direction.is_near_zero() {
-            direction = collision.normal;
-        }
-        result.scattered_ray = Ray::new(collision.point, direction);
-        result.attenuation = self.albedo.clone();
-        result.outcome = ScatterOutcome::Scattered;
-        result
+    fn eval(&self, wi: Vec3, _: Vec3, collision: &Collision) -> Color {
+        let albedo = self.albedo.value(collision.uv, collision.point);
+        albedo * (collision.normal.dot_product(wi.unit()).max(0.) / PI)
+    }
+
+    fn sample(&self, _: Vec3, collision: &Collision) -> Option<BsdfSample> {
+        let (u, v) = orthonormal_basis(collision.normal);
+        let local = Vec3::random_cosine_direction();
+        let direction = local.x * u + local.y * v + local.z * collision.normal;
+        let pdf = local.z / PI;
+        (pdf > 0.).then(|| BsdfSample {
+            direction,
+            pdf,
+            // the cosine-weighted density cancels everything but the albedo
+            weight: self.albedo.value(collision.uv, collision.point),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, wi: Vec3, _: Vec3, collision: &Collision) -> f64 {
+        collision.normal.dot_product(wi.unit()).max(0.) / PI
+    }
+
+    fn albedo(&self, collision: &Collision) -> Color {
+        self.albedo.value(collision.uv, collision.point)
     }
 }
 
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzz: f64,
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzz: f64) -> Self {
+    pub fn new(albedo: impl Texture + 'static, fuzz: f64) -> Self {
         Self {
-            albedo,
+            albedo: Arc::new(albedo),
             fuzz: if fuzz < 1. { fuzz } else { 1. },
         }
     }
-    pub fn new_arc(albedo: Color, fuzz: f64) -> Arc<Self> {
+    pub fn new_arc(albedo: impl Texture + 'static, fuzz: f64) -> Arc<Self> {
         Arc::new(Self::new(albedo, fuzz))
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, collision: &Collision) -> ScatterResult {
-        let mut result = ScatterResult::default();
-        let reflected = ray_in.direction.unit().reflect(collision.normal);
-        result.scattered_ray = Ray::new(
-            collision.point,
-            reflected + self.fuzz * Vec3::random_unit_vector(),
-        );
-        result.attenuation = self.albedo.clone();
-        result.outcome = if result.scattered_ray.direction.dot_product(collision.normal) > 0. {
-            ScatterOutcome::Scattered
-        } else {
-            ScatterOutcome::Absorbed
-        };
-        result
+    fn sample(&self, wo: Vec3, collision: &Collision) -> Option<BsdfSample> {
+        // the fuzz perturbation has no tractable density, so it is treated as a delta lobe too
+        let reflected = (-wo).unit().reflect(collision.normal);
+        let direction = (reflected + self.fuzz * Vec3::random_unit_vector()).unit();
+        (direction.dot_product(collision.normal) > 0.).then(|| BsdfSample {
+            direction,
+            pdf: 1.,
+            weight: self.albedo.value(collision.uv, collision.point),
+            delta: true,
+        })
+    }
+
+    fn albedo(&self, collision: &Collision) -> Color {
+        self.albedo.value(collision.uv, collision.point)
     }
 }
 
@@ -101,26 +150,139 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, collision: &Collision) -> ScatterResult {
-        let mut result = ScatterResult::default();
+    fn sample(&self, wo: Vec3, collision: &Collision) -> Option<BsdfSample> {
         let rr = match collision.facing {
             Facing::Front => 1. / self.ir,
             Facing::Back => self.ir,
         };
-        let unit_direction = ray_in.direction.unit();
+        let unit_direction = (-wo).unit();
         let cos_theta = f64::min((-unit_direction).dot_product(collision.normal), 1.);
         let sin_theta = (1. - cos_theta.powf(2.)).sqrt();
         let cannot_refract = rr * sin_theta > 1.;
-        let direction: Vec3 =
-            if cannot_refract || Dielectric::schlick_approximation(cos_theta, rr) > rand() {
-                unit_direction.reflect(collision.normal)
-            } else {
-                unit_direction.refract(collision.normal, rr)
-            };
-
-        result.scattered_ray = Ray::new(collision.point, direction);
-        result.attenuation = Color::all(1.);
-        result.outcome = ScatterOutcome::Scattered;
-        result
+
+        // reflection and refraction are picked in proportion to the Fresnel term, which
+        // then cancels out of the weight
+        let reflectance = if cannot_refract {
+            1.
+        } else {
+            Dielectric::schlick_approximation(cos_theta, rr)
+        };
+        let (direction, pdf) = if reflectance > rand() {
+            (unit_direction.reflect(collision.normal), reflectance)
+        } else {
+            (
+                unit_direction.refract(collision.normal, rr),
+                1. - reflectance,
+            )
+        };
+
+        Some(BsdfSample {
+            direction,
+            pdf,
+            weight: Color::all(1.),
+            delta: true,
+        })
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: impl Texture + 'static) -> Self {
+        Self {
+            emit: Arc::new(emit),
+        }
+    }
+    pub fn new_arc(emit: impl Texture + 'static) -> Arc<Self> {
+        Arc::new(Self::new(emit))
+    }
+}
+
+impl Material for DiffuseLight {
+    fn sample(&self, _: Vec3, _: &Collision) -> Option<BsdfSample> {
+        None
+    }
+
+    /// Light leaves the front face only, the side the outward normal points to.
+    fn emitted(&self, collision: &Collision) -> Color {
+        match collision.facing {
+            Facing::Front => self.emit.value(collision.uv, collision.point),
+            Facing::Back => Color::black(),
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{seed_rng, Point};
+
+    fn assert_close(a: f64, b: f64, what: &str) {
+        assert!(
+            (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1e-12),
+            "{what}: {a} != {b}"
+        );
+    }
+
+    /// Samples `material` for many viewing directions around a tilted normal, checking that
+    /// every sample agrees with `eval` and `pdf`, and returns the mean weight: the fraction of
+    /// light the surface scatters, which a white furnace bounds by one.
+    pub fn check_samples(material: Arc<dyn Material>, facing: Facing) -> Color {
+        seed_rng(7);
+        let normal = Vec3::new(1., 2., 3.).unit();
+        let collision = Collision::new(Point::all(0.), normal, 1., facing, material.clone());
+        let (views, samples_per_view) = (200, 100);
+        let mut total = Color::black();
+        for _ in 0..views {
+            let wo = Vec3::random_unit_vector();
+            let wo = if wo.dot_product(normal) < 0. { -wo } else { wo };
+            for _ in 0..samples_per_view {
+                let Some(sample) = material.sample(wo, &collision) else {
+                    continue;
+                };
+                if !sample.delta {
+                    let pdf = material.pdf(sample.direction, wo, &collision);
+                    assert_close(sample.pdf, pdf, "pdf");
+                    let eval = material.eval(sample.direction, wo, &collision);
+                    assert_close(sample.weight.r, eval.r / pdf, "red weight");
+                    assert_close(sample.weight.g, eval.g / pdf, "green weight");
+                    assert_close(sample.weight.b, eval.b / pdf, "blue weight");
+                }
+                total = total + sample.weight;
+            }
+        }
+        total / f64::from(views * samples_per_view)
+    }
+
+    #[test]
+    fn lambertian_samples_match_eval_and_pdf() {
+        let albedo = check_samples(
+            Lambertian::new_arc(Color::new(0.2, 0.5, 0.8)),
+            Facing::Front,
+        );
+        assert_close(albedo.r, 0.2, "red albedo");
+        assert_close(albedo.b, 0.8, "blue albedo");
+    }
+
+    #[test]
+    fn diffuse_lights_shine_from_the_front_face_only() {
+        let light = DiffuseLight::new_arc(Color::new(4., 5., 6.));
+        assert!(light.is_emissive());
+        assert!(!Lambertian::new(Color::white()).is_emissive());
+
+        let hit =
+            |facing| Collision::new(Point::all(0.), Vec3::from_z(1.), 1., facing, light.clone());
+        let front = light.emitted(&hit(Facing::Front));
+        assert_eq!((front.r, front.g, front.b), (4., 5., 6.));
+        assert_eq!(light.emitted(&hit(Facing::Back)).luminance(), 0.);
+        assert!(light
+            .sample(Vec3::from_z(1.), &hit(Facing::Front))
+            .is_none());
     }
 }
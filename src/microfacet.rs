@@ -0,0 +1,481 @@
+use crate::{orthonormal_basis, rand, BsdfSample, Collision, Color, Facing, Material, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Below this the lobe is too narrow to evaluate reliably and is treated as a perfect mirror.
+const MIN_ALPHA: f64 = 1e-3;
+
+/// Expresses vectors in the frame whose +z is the shading normal.
+#[derive(Clone, Copy)]
+pub struct ShadingFrame {
+    u: Vec3,
+    v: Vec3,
+    n: Vec3,
+}
+
+impl ShadingFrame {
+    pub fn new(normal: Vec3) -> Self {
+        let (u, v) = orthonormal_basis(normal);
+        Self { u, v, n: normal }
+    }
+
+    pub fn to_local(self, w: Vec3) -> Vec3 {
+        Vec3::new(
+            w.dot_product(self.u),
+            w.dot_product(self.v),
+            w.dot_product(self.n),
+        )
+    }
+
+    pub fn to_world(self, w: Vec3) -> Vec3 {
+        w.x * self.u + w.y * self.v + w.z * self.n
+    }
+}
+
+/// The GGX (Trowbridge-Reitz) distribution of microfacet normals, in the local shading frame.
+#[derive(Clone, Copy)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    /// `roughness` is perceptual; the distribution's width is its square.
+    pub fn new(roughness: f64) -> Self {
+        let roughness = roughness.clamp(0., 1.);
+        Self {
+            alpha: roughness * roughness,
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < MIN_ALPHA
+    }
+
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z <= 0. {
+            return 0.;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.z * h.z * (a2 - 1.) + 1.;
+        a2 / (PI * t * t)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0. {
+            return f64::INFINITY;
+        }
+        let tan2 = (1. - cos2).max(0.) / cos2;
+        0.5 * ((1. + self.alpha * self.alpha * tan2).sqrt() - 1.)
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// Height-correlated masking-shadowing.
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of [`Ggx::sample_visible`] returning `h` as seen from `wo`.
+    pub fn pdf_visible(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z <= 0. {
+            return 0.;
+        }
+        self.g1(wo) * wo.dot_product(h).max(0.) * self.d(h) / wo.z
+    }
+
+    /// Samples only the normals visible from `wo` (Heitz 2018), which wastes no samples on
+    /// facets facing away from the viewer.
+    pub fn sample_visible(&self, wo: Vec3) -> Vec3 {
+        let vh = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).unit();
+        let len_sq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len_sq > 0. {
+            Vec3::new(-vh.y, vh.x, 0.) / len_sq.sqrt()
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let t2 = vh.cross(t1);
+
+        let r = rand().sqrt();
+        let phi = 2. * PI * rand();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + vh.z);
+        let p2 = (1. - s) * (1. - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * vh;
+
+        Vec3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(0.)).unit()
+    }
+
+    /// Mirrors `wo` about a visible normal, `None` if that sends it below the surface.
+    pub fn sample_reflection(&self, wo: Vec3) -> Option<Vec3> {
+        let wi = reflect(wo, self.sample_visible(wo));
+        (wi.z > 0.).then_some(wi)
+    }
+
+    /// Density of [`Ggx::sample_reflection`] returning `wi`.
+    pub fn reflection_pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wi.z <= 0. || wo.z <= 0. {
+            return 0.;
+        }
+        let h = (wi + wo).unit();
+        self.pdf_visible(wo, h) / (4. * wo.dot_product(h))
+    }
+}
+
+/// Unpolarized reflectance of a conductor with complex index of refraction `eta + i k`.
+pub fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_i = cos_i.clamp(0., 1.);
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1. - cos2;
+
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2b2 + t0)).max(0.).sqrt();
+
+    let t1 = a2b2 + cos2;
+    let t2 = 2. * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rs + rp)
+}
+
+/// Unpolarized reflectance of a dielectric interface, `eta` being the ratio of the
+/// transmitted to the incident side's index.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0., 1.);
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+fn reflect(wo: Vec3, h: Vec3) -> Vec3 {
+    2. * wo.dot_product(h) * h - wo
+}
+
+/// Bends `wo` through the microfacet `h` (`wo . h > 0`), `None` on total internal reflection.
+fn refract(wo: Vec3, h: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = wo.dot_product(h);
+    let sin2_t = (1. - cos_i * cos_i).max(0.) / (eta * eta);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * h)
+}
+
+/// A rough metal reflecting with the Fresnel term of its complex index of refraction, given
+/// per RGB channel.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    ggx: Ggx,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self {
+            eta,
+            k,
+            ggx: Ggx::new(roughness),
+        }
+    }
+    pub fn new_arc(eta: Color, k: Color, roughness: f64) -> Arc<Self> {
+        Arc::new(Self::new(eta, k, roughness))
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.143, 0.375, 1.442),
+            Color::new(3.983, 2.386, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.913, 2.453, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    /// Looks up `gold`, `copper` or `aluminium` by name.
+    pub fn preset(name: &str, roughness: f64) -> Option<Self> {
+        match name {
+            "gold" => Some(Self::gold(roughness)),
+            "copper" => Some(Self::copper(roughness)),
+            "aluminium" | "aluminum" => Some(Self::aluminium(roughness)),
+            _ => None,
+        }
+    }
+
+    pub fn eta(&self) -> &Color {
+        &self.eta
+    }
+
+    pub fn k(&self) -> &Color {
+        &self.k
+    }
+
+    fn fresnel(&self, cos_i: f64) -> Color {
+        Color::new(
+            fresnel_conductor(cos_i, self.eta.r, self.k.r),
+            fresnel_conductor(cos_i, self.eta.g, self.k.g),
+            fresnel_conductor(cos_i, self.eta.b, self.k.b),
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn eval(&self, wi: Vec3, wo: Vec3, collision: &Collision) -> Color {
+        let frame = ShadingFrame::new(collision.normal);
+        let (wi, wo) = (frame.to_local(wi.unit()), frame.to_local(wo.unit()));
+        if self.ggx.is_smooth() || wi.z <= 0. || wo.z <= 0. {
+            return Color::black();
+        }
+        let h = (wi + wo).unit();
+        self.fresnel(wo.dot_product(h)) * (self.ggx.d(h) * self.ggx.g2(wo, wi) / (4. * wo.z))
+    }
+
+    fn sample(&self, wo: Vec3, collision: &Collision) -> Option<BsdfSample> {
+        let frame = ShadingFrame::new(collision.normal);
+        let wo = frame.to_local(wo.unit());
+        if wo.z <= 0. {
+            return None;
+        }
+
+        if self.ggx.is_smooth() {
+            return Some(BsdfSample {
+                direction: frame.to_world(Vec3::new(-wo.x, -wo.y, wo.z)),
+                pdf: 1.,
+                weight: self.fresnel(wo.z),
+                delta: true,
+            });
+        }
+
+        let wi = self.ggx.sample_reflection(wo)?;
+        let h = (wi + wo).unit();
+        Some(BsdfSample {
+            direction: frame.to_world(wi),
+            pdf: self.ggx.reflection_pdf(wo, wi),
+            weight: self.fresnel(wo.dot_product(h)) * (self.ggx.g2(wo, wi) / self.ggx.g1(wo)),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, wi: Vec3, wo: Vec3, collision: &Collision) -> f64 {
+        let frame = ShadingFrame::new(collision.normal);
+        let (wi, wo) = (frame.to_local(wi.unit()), frame.to_local(wo.unit()));
+        if self.ggx.is_smooth() {
+            return 0.;
+        }
+        self.ggx.reflection_pdf(wo, wi)
+    }
+
+    /// Head-on reflectance.
+    fn albedo(&self, _: &Collision) -> Color {
+        self.fresnel(1.)
+    }
+}
+
+/// Frosted glass: GGX reflection and transmission (Walter et al. 2007).
+pub struct RoughDielectric {
+    ior: f64,
+    ggx: Ggx,
+}
+
+impl RoughDielectric {
+    pub fn new(ior: f64, roughness: f64) -> Self {
+        Self {
+            ior,
+            ggx: Ggx::new(roughness),
+        }
+    }
+    pub fn new_arc(ior: f64, roughness: f64) -> Arc<Self> {
+        Arc::new(Self::new(ior, roughness))
+    }
+
+    /// Ratio of the index across the surface to the index on the viewer's side.
+    pub fn eta(&self, collision: &Collision) -> f64 {
+        match collision.facing {
+            Facing::Front => self.ior,
+            Facing::Back => 1. / self.ior,
+        }
+    }
+
+    /// The microfacet normal taking `wo` to `wi`, with the relative index along the way;
+    /// `None` for pairs no facet facing the viewer connects.
+    fn half_vector(&self, wi: Vec3, wo: Vec3, eta: f64) -> Option<(Vec3, f64)> {
+        let reflected = wi.z > 0.;
+        let etap = if reflected { 1. } else { eta };
+        let h = wi * etap + wo;
+        if h.is_near_zero() {
+            return None;
+        }
+        let h = if h.z < 0. { -h.unit() } else { h.unit() };
+        // facets must be seen from the front by wo, and by wi on its own side
+        let wi_side = wi.dot_product(h) * wi.z;
+        (wo.dot_product(h) > 0. && wi_side > 0.).then_some((h, etap))
+    }
+
+    /// Picks reflection or refraction through a visible facet in proportion to its Fresnel
+    /// term, in the local frame. Only meaningful for rough surfaces.
+    pub fn sample_local(&self, wo: Vec3, eta: f64) -> Option<Vec3> {
+        let h = self.ggx.sample_visible(wo);
+        let fresnel = fresnel_dielectric(wo.dot_product(h), eta);
+        let wi = match refract(wo, h, eta) {
+            Some(wi) if rand() >= fresnel => wi,
+            _ => reflect(wo, h),
+        };
+        // a reflection or refraction through a steep facet can end up on the wrong side
+        (wi.z != 0. && (wi.z > 0.) == (wi.dot_product(h) > 0.)).then_some(wi)
+    }
+
+    /// BSDF times cosine in the local frame, for rough surfaces.
+    pub fn eval_local(&self, wi: Vec3, wo: Vec3, eta: f64) -> f64 {
+        if wo.z <= 0. || wi.z == 0. {
+            return 0.;
+        }
+        let Some((h, etap)) = self.half_vector(wi, wo, eta) else {
+            return 0.;
+        };
+
+        let fresnel = fresnel_dielectric(wo.dot_product(h), eta);
+        let dg = self.ggx.d(h) * self.ggx.g2(wo, wi);
+        if wi.z > 0. {
+            fresnel * dg / (4. * wo.z)
+        } else {
+            let denom = wi.dot_product(h) + wo.dot_product(h) / etap;
+            let jacobian = (wi.dot_product(h) * wo.dot_product(h)).abs() / (denom * denom);
+            // radiance is compressed by the squared index ratio as it enters a denser medium
+            (1. - fresnel) * dg * jacobian / (wo.z * etap * etap)
+        }
+    }
+
+    /// Density of [`RoughDielectric::sample_local`] returning `wi`.
+    pub fn pdf_local(&self, wi: Vec3, wo: Vec3, eta: f64) -> f64 {
+        if wo.z <= 0. || wi.z == 0. {
+            return 0.;
+        }
+        let Some((h, etap)) = self.half_vector(wi, wo, eta) else {
+            return 0.;
+        };
+        let fresnel = fresnel_dielectric(wo.dot_product(h), eta);
+        let visible = self.ggx.pdf_visible(wo, h);
+        if wi.z > 0. {
+            fresnel * visible / (4. * wo.dot_product(h))
+        } else {
+            let denom = wi.dot_product(h) + wo.dot_product(h) / etap;
+            (1. - fresnel) * visible * wi.dot_product(h).abs() / (denom * denom)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn eval(&self, wi: Vec3, wo: Vec3, collision: &Collision) -> Color {
+        let frame = ShadingFrame::new(collision.normal);
+        let (wi, wo) = (frame.to_local(wi.unit()), frame.to_local(wo.unit()));
+        if self.ggx.is_smooth() {
+            return Color::black();
+        }
+        Color::all(self.eval_local(wi, wo, self.eta(collision)))
+    }
+
+    fn sample(&self, wo: Vec3, collision: &Collision) -> Option<BsdfSample> {
+        let frame = ShadingFrame::new(collision.normal);
+        let wo = frame.to_local(wo.unit());
+        if wo.z <= 0. {
+            return None;
+        }
+        let eta = self.eta(collision);
+
+        if self.ggx.is_smooth() {
+            let fresnel = fresnel_dielectric(wo.z, eta);
+            let normal = Vec3::new(0., 0., 1.);
+            let (wi, pdf, weight) = match refract(wo, normal, eta) {
+                Some(wi) if rand() >= fresnel => (wi, 1. - fresnel, 1. / (eta * eta)),
+                _ => (reflect(wo, normal), fresnel, 1.),
+            };
+            return Some(BsdfSample {
+                direction: frame.to_world(wi),
+                pdf,
+                weight: Color::all(weight),
+                delta: true,
+            });
+        }
+
+        let wi = self.sample_local(wo, eta)?;
+        let pdf = self.pdf_local(wi, wo, eta);
+        (pdf > 0.).then(|| BsdfSample {
+            direction: frame.to_world(wi),
+            pdf,
+            weight: Color::all(self.eval_local(wi, wo, eta) / pdf),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, wi: Vec3, wo: Vec3, collision: &Collision) -> f64 {
+        let frame = ShadingFrame::new(collision.normal);
+        let (wi, wo) = (frame.to_local(wi.unit()), frame.to_local(wo.unit()));
+        if self.ggx.is_smooth() {
+            return 0.;
+        }
+        self.pdf_local(wi, wo, self.eta(collision))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::check_samples;
+
+    #[test]
+    fn conductor_samples_match_eval_and_pdf() {
+        for roughness in [0.1, 0.5, 1.] {
+            for conductor in [Conductor::gold(roughness), Conductor::aluminium(roughness)] {
+                let albedo = check_samples(Arc::new(conductor), Facing::Front);
+                // masking loses energy, so no channel may come back brighter than it went in
+                for channel in [albedo.r, albedo.g, albedo.b] {
+                    assert!(channel > 0. && channel <= 1., "albedo {channel}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_perfect_conductor_passes_the_white_furnace() {
+        let conductor = Conductor::new(Color::all(1.), Color::all(1e4), 0.5);
+        let albedo = check_samples(Arc::new(conductor), Facing::Front);
+        assert!(albedo.g > 0.8 && albedo.g <= 1., "albedo {}", albedo.g);
+    }
+
+    #[test]
+    fn rough_dielectric_samples_match_eval_and_pdf() {
+        for roughness in [0.1, 0.5, 1.] {
+            let glass = RoughDielectric::new_arc(1.5, roughness);
+            let albedo = check_samples(glass.clone(), Facing::Front);
+            // light entering the glass is compressed into a smaller solid angle, dimming it
+            assert!(albedo.r > 0.3 && albedo.r <= 1., "albedo {}", albedo.r);
+            check_samples(glass, Facing::Back);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{Color, Point, Texture, Vec3};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::sync::Arc;
+
+const POINT_COUNT: usize = 256;
+
+fn hermite(t: f64) -> f64 {
+    t * t * (3. - 2. * t)
+}
+
+/// Gradient noise on the integer lattice (Perlin 1985 with random unit gradients), smoothly
+/// interpolated between lattice points. Values fall roughly in `[-1, 1]`.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    /// The same seed always gives the same noise, whichever thread evaluates it.
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                let v = Vec3::new(
+                    rng.gen_range(-1. ..1.),
+                    rng.gen_range(-1. ..1.),
+                    rng.gen_range(-1. ..1.),
+                );
+                if v.is_near_zero() {
+                    Vec3::new(1., 0., 0.)
+                } else {
+                    v.unit()
+                }
+            })
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        let perm = [permutation(), permutation(), permutation()];
+        Self { gradients, perm }
+    }
+
+    fn gradient(&self, i: i64, j: i64, k: i64) -> Vec3 {
+        let wrap = |n: i64| (n & (POINT_COUNT as i64 - 1)) as usize;
+        self.gradients[self.perm[0][wrap(i)] ^ self.perm[1][wrap(j)] ^ self.perm[2][wrap(k)]]
+    }
+
+    pub fn noise(&self, p: Point) -> f64 {
+        let (i, j, k) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - i, p.y - j, p.z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+        let (uu, vv, ww) = (hermite(u), hermite(v), hermite(w));
+
+        // trilinear blend of each corner's gradient dotted with the offset to it
+        let mut sum = 0.;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let offset = Vec3::new(u - di as f64, v - dj as f64, w - dk as f64);
+                    let weight = (if di == 1 { uu } else { 1. - uu })
+                        * (if dj == 1 { vv } else { 1. - vv })
+                        * (if dk == 1 { ww } else { 1. - ww });
+                    sum += weight * self.gradient(i + di, j + dj, k + dk).dot_product(offset);
+                }
+            }
+        }
+        sum
+    }
+
+    /// Fractional Brownian motion: `octaves` layers of noise, each at twice the frequency
+    /// and half the amplitude of the last.
+    pub fn fbm(&self, p: Point, octaves: u32) -> f64 {
+        let (mut sum, mut amplitude, mut p) = (0., 1., p);
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(p);
+            amplitude *= 0.5;
+            p = 2. * p;
+        }
+        sum
+    }
+
+    /// Like [`Perlin::fbm`] but summing absolute values, which gives sharp creases.
+    pub fn turbulence(&self, p: Point, octaves: u32) -> f64 {
+        let (mut sum, mut amplitude, mut p) = (0., 1., p);
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(p).abs();
+            amplitude *= 0.5;
+            p = 2. * p;
+        }
+        sum
+    }
+}
+
+/// Cellular noise (Worley 1996): the distance to the nearest of one random feature point
+/// per unit cell.
+pub struct Worley {
+    seed: u64,
+}
+
+impl Worley {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// SplitMix64 finalizer over the cell coordinates and seed.
+    fn hash(&self, i: i64, j: i64, k: i64, salt: u64) -> u64 {
+        let mut z = self
+            .seed
+            .wrapping_add((i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .wrapping_add((j as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f))
+            .wrapping_add((k as u64).wrapping_mul(0x1656_67b1_9e37_79f9))
+            .wrapping_add(salt.wrapping_mul(0xd6e8_feb8_6659_fd93));
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn feature_point(&self, i: i64, j: i64, k: i64) -> Point {
+        let unit = |salt| (self.hash(i, j, k, salt) >> 11) as f64 / (1u64 << 53) as f64;
+        Point::new(i as f64 + unit(0), j as f64 + unit(1), k as f64 + unit(2))
+    }
+
+    /// Distance to the nearest feature point, mostly within `[0, 1]`.
+    pub fn noise(&self, p: Point) -> f64 {
+        let (i, j, k) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+        let mut nearest = f64::INFINITY;
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let feature = self.feature_point(i + di, j + dj, k + dk);
+                    nearest = nearest.min((feature - p).len_sq());
+                }
+            }
+        }
+        nearest.sqrt()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoisePattern {
+    Perlin,
+    Fbm {
+        octaves: u32,
+    },
+    Turbulence {
+        octaves: u32,
+    },
+    Worley,
+    /// Veins from a sine wave along z, warped by turbulence.
+    Marble {
+        octaves: u32,
+    },
+    /// Rings around the y axis, disturbed by fBm.
+    Wood {
+        octaves: u32,
+    },
+}
+
+/// Blends between two textures by a 3D noise pattern evaluated at the hit point, so the
+/// surface looks carved from a solid block regardless of its UVs.
+pub struct NoiseTexture {
+    pattern: NoisePattern,
+    perlin: Perlin,
+    worley: Worley,
+    scale: f64,
+    low: Arc<dyn Texture>,
+    high: Arc<dyn Texture>,
+}
+
+impl NoiseTexture {
+    pub fn new(pattern: NoisePattern, seed: u64) -> Self {
+        Self {
+            pattern,
+            perlin: Perlin::new(seed),
+            worley: Worley::new(seed),
+            scale: 1.,
+            low: Arc::new(Color::black()),
+            high: Arc::new(Color::white()),
+        }
+    }
+
+    pub fn marble(seed: u64) -> Self {
+        Self::new(NoisePattern::Marble { octaves: 7 }, seed)
+            .low(Color::new(0.2, 0.2, 0.22))
+            .high(Color::new(0.92, 0.91, 0.88))
+    }
+
+    pub fn wood(seed: u64) -> Self {
+        Self::new(NoisePattern::Wood { octaves: 4 }, seed)
+            .low(Color::new(0.33, 0.18, 0.08))
+            .high(Color::new(0.68, 0.45, 0.24))
+    }
+
+    #[must_use]
+    pub fn pattern(self, pattern: NoisePattern) -> Self {
+        NoiseTexture { pattern, ..self }
+    }
+
+    /// Pattern features per unit of world space.
+    #[must_use]
+    pub fn scale(self, scale: f64) -> Self {
+        NoiseTexture { scale, ..self }
+    }
+
+    /// What the lowest pattern value maps to.
+    #[must_use]
+    pub fn low(self, low: impl Texture + 'static) -> Self {
+        NoiseTexture {
+            low: Arc::new(low),
+            ..self
+        }
+    }
+
+    /// What the highest pattern value maps to.
+    #[must_use]
+    pub fn high(self, high: impl Texture + 'static) -> Self {
+        NoiseTexture {
+            high: Arc::new(high),
+            ..self
+        }
+    }
+
+    /// The pattern at `point`, in `[0, 1]`.
+    pub fn pattern_value(&self, point: Point) -> f64 {
+        let p = self.scale * point;
+        let t = match self.pattern {
+            NoisePattern::Perlin => 0.5 * (1. + self.perlin.noise(p)),
+            NoisePattern::Fbm { octaves } => 0.5 * (1. + self.perlin.fbm(p, octaves)),
+            NoisePattern::Turbulence { octaves } => self.perlin.turbulence(p, octaves),
+            NoisePattern::Worley => self.worley.noise(p),
+            NoisePattern::Marble { octaves } => {
+                0.5 * (1. + (p.z + 10. * self.perlin.turbulence(p, octaves)).sin())
+            }
+            NoisePattern::Wood { octaves } => {
+                let rings = 4. * (p.x * p.x + p.z * p.z).sqrt() + 2. * self.perlin.fbm(p, octaves);
+                // sharpen each ring so the late wood is a thin dark band
+                1. - (rings - rings.floor()).powi(3)
+            }
+        };
+        t.clamp(0., 1.)
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, uv: (f64, f64), point: Point) -> Color {
+        let t = self.pattern_value(point);
+        self.low.value(uv, point) * (1. - t) + self.high.value(uv, point) * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_points(count: usize) -> Vec<Point> {
+        let mut rng = StdRng::seed_from_u64(9);
+        (0..count)
+            .map(|_| {
+                Point::new(
+                    rng.gen_range(-50. ..50.),
+                    rng.gen_range(-50. ..50.),
+                    rng.gen_range(-50. ..50.),
+                )
+            })
+            .collect()
+    }
+
+    /// Every kind of value a seed determines, at each of `points`.
+    fn values(seed: u64, points: &[Point]) -> Vec<f64> {
+        let (perlin, worley) = (Perlin::new(seed), Worley::new(seed));
+        let (marble, wood) = (NoiseTexture::marble(seed), NoiseTexture::wood(seed));
+        points
+            .iter()
+            .flat_map(|&p| {
+                [
+                    perlin.noise(p),
+                    worley.noise(p),
+                    marble.pattern_value(p),
+                    wood.pattern_value(p),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seeds_determine_the_noise() {
+        let points = random_points(200);
+        let first = values(42, &points);
+        assert_eq!(first, values(42, &points));
+
+        let other = values(43, &points);
+        for kind in 0..4 {
+            let differing = (kind..first.len())
+                .step_by(4)
+                .filter(|&i| first[i] != other[i])
+                .count();
+            assert!(
+                differing > 150,
+                "only {differing} values of kind {kind} changed"
+            );
+        }
+    }
+
+    #[test]
+    fn values_stay_in_range() {
+        let patterns = [
+            NoisePattern::Perlin,
+            NoisePattern::Fbm { octaves: 5 },
+            NoisePattern::Turbulence { octaves: 5 },
+            NoisePattern::Worley,
+            NoisePattern::Marble { octaves: 7 },
+            NoisePattern::Wood { octaves: 4 },
+        ];
+        let points = random_points(2000);
+        for pattern in patterns {
+            let texture = NoiseTexture::new(pattern, 1).scale(0.7);
+            let values: Vec<f64> = points.iter().map(|&p| texture.pattern_value(p)).collect();
+            assert!(values.iter().all(|t| (0. ..=1.).contains(t)), "{pattern:?}");
+            // and they make use of it, rather than sitting at a constant
+            let (low, high) = values
+                .iter()
+                .fold((1f64, 0f64), |(low, high), &t| (low.min(t), high.max(t)));
+            assert!(high - low > 0.5, "{pattern:?} spans only {low}..{high}");
+        }
+
+        let (perlin, worley) = (Perlin::new(1), Worley::new(1));
+        for &p in &points {
+            assert!(perlin.noise(p).abs() <= 1.);
+            assert!((0. ..=3f64.sqrt()).contains(&worley.noise(p)));
+        }
+    }
+
+    #[test]
+    fn perlin_noise_vanishes_on_the_lattice() {
+        let perlin = Perlin::new(7);
+        for i in -3..=3 {
+            for j in -3..=3 {
+                for k in [-300, -1, 0, 1, 255, 256] {
+                    let p = Point::new(f64::from(i), f64::from(j), f64::from(k));
+                    assert_eq!(perlin.noise(p), 0., "at {p:?}");
+                }
+            }
+        }
+        assert_ne!(perlin.noise(Point::new(0.5, 0.25, 0.75)), 0.);
+    }
+}
@@ -0,0 +1,480 @@
+use crate::{
+    Color, Dielectric, DiffuseLight, Lambertian, Material, Metal, Point, TriangleMesh, Vec3,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub type MtlLibrary = HashMap<String, Arc<dyn Material>>;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{file}:{line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io(_, err) => Some(err),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Iterates the meaningful lines of an OBJ or MTL file as `(line number, keyword, arguments)`.
+fn statements(source: &str) -> impl Iterator<Item = (usize, &str, Vec<&str>)> {
+    source.lines().enumerate().filter_map(|(i, line)| {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let keyword = words.next()?;
+        Some((i + 1, keyword, words.collect()))
+    })
+}
+
+fn parse_floats(args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, String> {
+    if args.len() < min || args.len() > max {
+        return Err(if min == max {
+            format!("expected {min} numbers, found {}", args.len())
+        } else {
+            format!("expected {min} to {max} numbers, found {}", args.len())
+        });
+    }
+    args.iter()
+        .map(|arg| {
+            arg.parse::<f64>()
+                .map_err(|_| format!("'{arg}' is not a number"))
+        })
+        .collect()
+}
+
+fn parse_color(args: &[&str]) -> Result<Color, String> {
+    // a single value means a grey
+    let v = parse_floats(args, 1, 3)?;
+    Ok(match v[..] {
+        [c] => Color::all(c),
+        [r, g, b] => Color::new(r, g, b),
+        _ => return Err("expected 1 or 3 color components".to_owned()),
+    })
+}
+
+#[derive(Clone)]
+pub struct MtlMaterial {
+    pub diffuse: Color,
+    pub specular: Color,
+    pub emission: Color,
+    pub shininess: f64,
+    pub ior: Option<f64>,
+    pub dissolve: f64,
+    pub illum: u32,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::all(0.8),
+            specular: Color::black(),
+            emission: Color::black(),
+            shininess: 0.,
+            ior: None,
+            dissolve: 1.,
+            illum: 2,
+        }
+    }
+}
+
+impl MtlMaterial {
+    /// Emissive materials become lights, transparent illumination models (and any `d < 1`)
+    /// become glass, mirror models become metal with a fuzz derived from `Ns`, and everything
+    /// else is diffuse.
+    pub fn to_material(&self) -> Arc<dyn Material> {
+        let Color { r, g, b } = self.emission;
+        match self.illum {
+            _ if r + g + b > 0. => DiffuseLight::new_arc(self.emission.clone()),
+            _ if self.dissolve < 1. => Dielectric::new_arc(self.ior.unwrap_or(1.5)),
+            4 | 6 | 7 | 9 => Dielectric::new_arc(self.ior.unwrap_or(1.5)),
+            3 | 5 | 8 => {
+                let Color { r, g, b } = self.specular;
+                let albedo = if r + g + b > 0. {
+                    self.specular.clone()
+                } else {
+                    self.diffuse.clone()
+                };
+                let fuzz = (2. / (self.shininess.max(0.) + 2.)).sqrt();
+                Metal::new_arc(albedo, fuzz)
+            }
+            _ => Lambertian::new_arc(self.diffuse.clone()),
+        }
+    }
+}
+
+pub fn parse_mtl(file: &str, source: &str) -> Result<MtlLibrary, ObjError> {
+    let mut parsed: Vec<(String, MtlMaterial)> = Vec::new();
+
+    for (line, keyword, args) in statements(source) {
+        let error = |message: String| ObjError::Parse {
+            file: file.to_owned(),
+            line,
+            message,
+        };
+
+        if keyword == "newmtl" {
+            if args.len() != 1 {
+                return Err(error("newmtl expects exactly one name".to_owned()));
+            }
+            parsed.push((args[0].to_owned(), MtlMaterial::default()));
+            continue;
+        }
+
+        let Some((_, current)) = parsed.last_mut() else {
+            return Err(error(format!("'{keyword}' appears before any newmtl")));
+        };
+
+        match keyword {
+            "Kd" => current.diffuse = parse_color(&args).map_err(error)?,
+            "Ks" => current.specular = parse_color(&args).map_err(error)?,
+            "Ke" => current.emission = parse_color(&args).map_err(error)?,
+            "Ns" => current.shininess = parse_floats(&args, 1, 1).map_err(error)?[0],
+            "Ni" => current.ior = Some(parse_floats(&args, 1, 1).map_err(error)?[0]),
+            "d" => current.dissolve = parse_floats(&args, 1, 1).map_err(error)?[0],
+            "Tr" => current.dissolve = 1. - parse_floats(&args, 1, 1).map_err(error)?[0],
+            "illum" => {
+                current.illum = match args[..] {
+                    [arg] => arg
+                        .parse()
+                        .map_err(|_| error(format!("'{arg}' is not an illumination model")))?,
+                    _ => return Err(error("illum expects exactly one value".to_owned())),
+                }
+            }
+            // texture maps and ambient terms have no counterpart yet
+            _ => {}
+        }
+    }
+
+    Ok(parsed
+        .into_iter()
+        .map(|(name, mtl)| (name, mtl.to_material()))
+        .collect())
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Point>,
+    normals: Vec<Option<Vec3>>,
+    uvs: Vec<Option<(f64, f64)>>,
+    indices: Vec<[usize; 3]>,
+    lookup: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, key: (usize, Option<usize>, Option<usize>), data: &ObjData) -> usize {
+        *self.lookup.entry(key).or_insert_with(|| {
+            let (v, vt, vn) = key;
+            self.positions.push(data.positions[v]);
+            self.uvs.push(vt.map(|i| data.uvs[i]));
+            self.normals.push(vn.map(|i| data.normals[i]));
+            self.positions.len() - 1
+        })
+    }
+
+    fn build(self, material: Arc<dyn Material>) -> TriangleMesh {
+        let mut mesh = TriangleMesh::new(self.positions, self.indices, material);
+        if let Some(normals) = self.normals.into_iter().collect::<Option<Vec<_>>>() {
+            mesh = mesh.normals(normals);
+        }
+        if let Some(uvs) = self.uvs.into_iter().collect::<Option<Vec<_>>>() {
+            mesh = mesh.uvs(uvs);
+        }
+        mesh
+    }
+}
+
+#[derive(Default)]
+struct ObjData {
+    positions: Vec<Point>,
+    uvs: Vec<(f64, f64)>,
+    normals: Vec<Vec3>,
+}
+
+/// Resolves a 1-based (or negative, relative) OBJ index into `0..len`.
+fn resolve_index(raw: &str, len: usize, what: &str) -> Result<usize, String> {
+    let index: i64 = raw
+        .parse()
+        .map_err(|_| format!("'{raw}' is not a valid {what} index"))?;
+    let resolved = match index {
+        0 => return Err(format!("{what} indices start at 1, found 0")),
+        i if i > 0 => i - 1,
+        i => len as i64 + i,
+    };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!(
+            "{what} index {index} is out of range, only {len} defined so far"
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+fn parse_face_vertex(
+    raw: &str,
+    data: &ObjData,
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let mut parts = raw.split('/');
+    let v = resolve_index(
+        parts.next().unwrap_or_default(),
+        data.positions.len(),
+        "vertex",
+    )?;
+    let vt = match parts.next() {
+        None | Some("") => None,
+        Some(raw) => Some(resolve_index(raw, data.uvs.len(), "texture coordinate")?),
+    };
+    let vn = match parts.next() {
+        None | Some("") => None,
+        Some(raw) => Some(resolve_index(raw, data.normals.len(), "normal")?),
+    };
+    if parts.next().is_some() {
+        return Err(format!("face vertex '{raw}' has too many components"));
+    }
+    Ok((v, vt, vn))
+}
+
+/// Parses OBJ geometry into one mesh per material. `mtllib` statements are left to
+/// [`load_obj`], so every `usemtl` name must already be in `materials`.
+pub fn parse_obj(
+    file: &str,
+    source: &str,
+    materials: &MtlLibrary,
+) -> Result<Vec<TriangleMesh>, ObjError> {
+    let default_material: Arc<dyn Material> = Lambertian::new_arc(Color::all(0.5));
+    let mut data = ObjData::default();
+    let mut groups: Vec<(Arc<dyn Material>, MeshBuilder)> = Vec::new();
+    let mut group_of: HashMap<Option<String>, usize> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for (line, keyword, args) in statements(source) {
+        let error = |message: String| ObjError::Parse {
+            file: file.to_owned(),
+            line,
+            message,
+        };
+
+        match keyword {
+            "v" => {
+                // an optional fourth weight component is ignored
+                let v = parse_floats(&args, 3, 4).map_err(error)?;
+                data.positions.push(Point::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = parse_floats(&args, 1, 3).map_err(error)?;
+                data.uvs.push((v[0], v.get(1).copied().unwrap_or(0.)));
+            }
+            "vn" => {
+                let v = parse_floats(&args, 3, 3).map_err(error)?;
+                data.normals.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(format!(
+                        "a face needs at least 3 vertices, found {}",
+                        args.len()
+                    )));
+                }
+                let keys = args
+                    .iter()
+                    .map(|raw| parse_face_vertex(raw, &data))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+
+                let group = *group_of.entry(current.clone()).or_insert_with(|| {
+                    let material = match &current {
+                        Some(name) => materials[name].clone(),
+                        None => default_material.clone(),
+                    };
+                    groups.push((material, MeshBuilder::default()));
+                    groups.len() - 1
+                });
+                let builder = &mut groups[group].1;
+                let vertices: Vec<usize> = keys
+                    .into_iter()
+                    .map(|key| builder.vertex(key, &data))
+                    .collect();
+
+                // polygons are assumed convex and split into a fan
+                for i in 1..vertices.len() - 1 {
+                    builder
+                        .indices
+                        .push([vertices[0], vertices[i], vertices[i + 1]]);
+                }
+            }
+            "usemtl" => match args[..] {
+                [name] if materials.contains_key(name) => current = Some(name.to_owned()),
+                [name] => return Err(error(format!("unknown material '{name}'"))),
+                _ => return Err(error("usemtl expects exactly one name".to_owned())),
+            },
+            "mtllib" if args.is_empty() => {
+                return Err(error("mtllib expects at least one file".to_owned()))
+            }
+            // grouping, smoothing groups, lines and curves carry nothing renderable
+            _ => {}
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|(material, builder)| builder.build(material))
+        .collect())
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|err| ObjError::Io(path.to_owned(), err))
+}
+
+/// Loads an OBJ file together with the MTL libraries it references, resolved relative to it.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<TriangleMesh>, ObjError> {
+    let path = path.as_ref();
+    let source = read(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut materials = MtlLibrary::new();
+    for (_, _, args) in statements(&source).filter(|(_, keyword, _)| *keyword == "mtllib") {
+        for lib in args {
+            let lib_path = dir.join(lib);
+            materials.extend(parse_mtl(
+                &lib_path.display().to_string(),
+                &read(&lib_path)?,
+            )?);
+        }
+    }
+
+    parse_obj(&path.display().to_string(), &source, &materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Bvh, Collidable, Collision, Ray};
+
+    const SQUARE: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+";
+
+    fn parse(source: &str) -> Result<Vec<TriangleMesh>, ObjError> {
+        parse_obj("test.obj", source, &MtlLibrary::new())
+    }
+
+    /// Where a ray straight down onto the z = 0 plane at `(x, y)` hits the mesh.
+    fn hit(mesh: TriangleMesh, x: f64, y: f64) -> Option<Collision> {
+        let ray = Ray::new(Point::new(x, y, 1.), Vec3::from_z(-1.));
+        mesh.into_bvh().collide(&ray, 0.001, f64::INFINITY)
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_latest_vertex() {
+        let meshes = parse(&format!("v 9 9 9\n{SQUARE}f -4 -3 -2\n")).unwrap();
+        assert_eq!(meshes.len(), 1);
+        let mesh = meshes.into_iter().next().unwrap();
+        assert_eq!(mesh.len(), 1);
+        assert!(hit(mesh, 0.75, 0.25).is_some());
+    }
+
+    #[test]
+    fn faces_with_uvs_and_normals() {
+        let source = format!("{SQUARE}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0.6 0.8\nf 1/1/1 2/2/1 3/3/1\n");
+        let collision = hit(parse(&source).unwrap().remove(0), 0.75, 0.25).unwrap();
+        assert!((collision.uv.0 - 0.75).abs() < 1e-12);
+        assert!((collision.uv.1 - 0.25).abs() < 1e-12);
+        assert!((collision.normal - Vec3::new(0., 0.6, 0.8)).len() < 1e-12);
+    }
+
+    #[test]
+    fn faces_with_normals_but_no_uvs() {
+        let source = format!("{SQUARE}vn 0.6 0 0.8\nf 1//1 2//1 3//1\n");
+        let collision = hit(parse(&source).unwrap().remove(0), 0.75, 0.25).unwrap();
+        assert!((collision.normal - Vec3::new(0.6, 0., 0.8)).len() < 1e-12);
+    }
+
+    #[test]
+    fn quads_are_split_into_two_triangles() {
+        let mesh = parse(&format!("{SQUARE}f 1 2 3 4\n")).unwrap().remove(0);
+        assert_eq!(mesh.len(), 2);
+        let triangles = mesh.into_triangles();
+        for (x, y) in [(0.75, 0.25), (0.25, 0.75)] {
+            let ray = Ray::new(Point::new(x, y, 1.), Vec3::from_z(-1.));
+            assert!(triangles.collide(&ray, 0.001, f64::INFINITY).is_some());
+        }
+    }
+
+    #[test]
+    fn bad_indices_are_reported_with_their_line() {
+        let message = |source: &str| parse(source).err().unwrap().to_string();
+        assert_eq!(
+            message(&format!("{SQUARE}f 0 1 2\n")),
+            "test.obj:5: vertex indices start at 1, found 0"
+        );
+        assert_eq!(
+            message(&format!("{SQUARE}\nf 1 2 5\n")),
+            "test.obj:6: vertex index 5 is out of range, only 4 defined so far"
+        );
+        assert_eq!(
+            message(&format!("{SQUARE}f 1 2 -5\n")),
+            "test.obj:5: vertex index -5 is out of range, only 4 defined so far"
+        );
+        assert_eq!(
+            message(&format!("{SQUARE}f 1/1 2 3\n")),
+            "test.obj:5: texture coordinate index 1 is out of range, only 0 defined so far"
+        );
+    }
+
+    #[test]
+    fn usemtl_groups_faces_into_one_mesh_per_material() {
+        let library =
+            parse_mtl("test.mtl", "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n").unwrap();
+        let source = format!(
+            "{SQUARE}v 2 0 0\nv 2 1 0\n\
+             usemtl red\nf 1 2 3\nusemtl blue\nf 2 5 6\nusemtl red\nf 1 3 4\n"
+        );
+        let meshes = parse_obj("test.obj", &source, &library).unwrap();
+        assert_eq!(
+            meshes.iter().map(|mesh| mesh.len()).collect::<Vec<_>>(),
+            [2, 1]
+        );
+
+        let mut meshes = meshes.into_iter();
+        let red = meshes.next().unwrap().into_bvh();
+        let blue = meshes.next().unwrap().into_bvh();
+        let material_at = |mesh: &Bvh, x: f64, y: f64| {
+            let ray = Ray::new(Point::new(x, y, 1.), Vec3::from_z(-1.));
+            mesh.collide(&ray, 0.001, f64::INFINITY).unwrap().material
+        };
+        assert!(Arc::ptr_eq(&material_at(&red, 0.75, 0.25), &library["red"]));
+        assert!(Arc::ptr_eq(&material_at(&red, 0.25, 0.75), &library["red"]));
+        assert!(Arc::ptr_eq(&material_at(&blue, 1.5, 0.5), &library["blue"]));
+
+        let unknown = parse_obj("test.obj", "usemtl green\n", &library);
+        assert_eq!(
+            unknown.err().unwrap().to_string(),
+            "test.obj:1: unknown material 'green'"
+        );
+    }
+}
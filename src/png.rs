@@ -0,0 +1,694 @@
+use crate::{
+    srgb_to_linear, zlib_compress, zlib_decompress, Color, Framebuffer, ImageError,
+    MAX_IMAGE_PIXELS,
+};
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+/// The CRC-32 PNG stores after every chunk, taken over its type and data.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Where each of the seven Adam7 passes starts and how far apart its pixels are, as
+/// `(x, y, dx, dy)`.
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+fn format_error<T>(message: impl Into<String>) -> Result<T, ImageError> {
+    Err(ImageError::Format(message.into()))
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, ImageError> {
+        if data.len() != 13 {
+            return format_error("bad PNG IHDR chunk");
+        }
+        let width = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+        let (bit_depth, color_type) = (data[8], data[9]);
+        let valid_depths: &[u8] = match color_type {
+            0 => &[1, 2, 4, 8, 16],
+            3 => &[1, 2, 4, 8],
+            2 | 4 | 6 => &[8, 16],
+            _ => return format_error(format!("unknown PNG color type {color_type}")),
+        };
+        if !valid_depths.contains(&bit_depth) {
+            return format_error(format!(
+                "bit depth {bit_depth} is not allowed for PNG color type {color_type}"
+            ));
+        }
+        if width == 0 || height == 0 {
+            return format_error("PNG image is empty");
+        }
+        if width
+            .checked_mul(height)
+            .is_none_or(|count| count > MAX_IMAGE_PIXELS)
+        {
+            return format_error(format!("PNG image size {width}x{height} is too large"));
+        }
+        if data[10] != 0 || data[11] != 0 {
+            return format_error("unknown PNG compression or filter method");
+        }
+        let interlaced = match data[12] {
+            0 => false,
+            1 => true,
+            method => return format_error(format!("unknown PNG interlace method {method}")),
+        };
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced,
+        })
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * usize::from(self.bit_depth)
+    }
+
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// The sub-images stored one after another, as `(x0, y0, dx, dy)`: every pixel `dx`
+    /// apart starting at `x0`, on every `dy`th row starting at `y0`.
+    fn passes(&self) -> &'static [(usize, usize, usize, usize)] {
+        if self.interlaced {
+            &ADAM7
+        } else {
+            &[(0, 0, 1, 1)]
+        }
+    }
+
+    /// Size of the decompressed image data, one filter byte plus the pixels per row.
+    fn data_len(&self) -> usize {
+        self.passes()
+            .iter()
+            .map(|&(x0, y0, dx, dy)| {
+                let width = (self.width + dx - x0 - 1) / dx;
+                let rows = (self.height + dy - y0 - 1) / dy;
+                if width == 0 {
+                    0
+                } else {
+                    rows * (self.row_bytes(width) + 1)
+                }
+            })
+            .sum()
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = (
+        (p - i16::from(a)).abs(),
+        (p - i16::from(b)).abs(),
+        (p - i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reverses the per-scanline filters, returning the rows without their filter bytes.
+fn unfilter(data: &[u8], row_bytes: usize, rows: usize, bpp: usize) -> Result<Vec<u8>, ImageError> {
+    if data.len() < rows * (row_bytes + 1) {
+        return format_error("PNG image data is truncated");
+    }
+    let mut out = vec![0u8; rows * row_bytes];
+    for y in 0..rows {
+        let filter = data[y * (row_bytes + 1)];
+        let source = &data[y * (row_bytes + 1) + 1..(y + 1) * (row_bytes + 1)];
+        let (previous, current) = out.split_at_mut(y * row_bytes);
+        let up = (y > 0).then(|| &previous[(y - 1) * row_bytes..]);
+        let current = &mut current[..row_bytes];
+        for x in 0..row_bytes {
+            let a = if x >= bpp { current[x - bpp] } else { 0 };
+            let b = up.map_or(0, |up| up[x]);
+            let c = match up {
+                Some(up) if x >= bpp => up[x - bpp],
+                _ => 0,
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return format_error(format!("unknown PNG filter type {filter}")),
+            };
+            current[x] = source[x].wrapping_add(predicted);
+        }
+    }
+    Ok(out)
+}
+
+/// Reads sample `index` of a row, scaled to `[0, 1]`.
+fn sample(row: &[u8], index: usize, bit_depth: u8) -> f64 {
+    match bit_depth {
+        16 => f64::from(u16::from_be_bytes([row[2 * index], row[2 * index + 1]])) / 65535.,
+        8 => f64::from(row[index]) / 255.,
+        _ => f64::from(raw_sample(row, index, bit_depth)) / f64::from((1u16 << bit_depth) - 1),
+    }
+}
+
+/// Sub-byte samples are packed with the leftmost pixel in the high bits.
+fn raw_sample(row: &[u8], index: usize, bit_depth: u8) -> u8 {
+    let bit = index * usize::from(bit_depth);
+    let shift = 8 - usize::from(bit_depth) - bit % 8;
+    ((u16::from(row[bit / 8]) >> shift) & ((1 << bit_depth) - 1)) as u8
+}
+
+/// Decodes a PNG image of any standard color type and bit depth into linear colors, treating
+/// the stored values as sRGB and ignoring transparency.
+pub fn read_png<R: Read>(mut reader: R) -> Result<Framebuffer, ImageError> {
+    let mut signature = [0u8; 8];
+    reader.read_exact(&mut signature)?;
+    if signature != PNG_SIGNATURE {
+        return format_error("not a PNG file");
+    }
+
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    loop {
+        let mut prefix = [0u8; 8];
+        reader.read_exact(&mut prefix)?;
+        let length = u32::from_be_bytes(prefix[0..4].try_into().unwrap());
+        // grown as the data arrives rather than trusting the length up front
+        let mut chunk = prefix[4..8].to_vec();
+        (&mut reader)
+            .take(u64::from(length))
+            .read_to_end(&mut chunk)?;
+        if chunk.len() < 4 + length as usize {
+            return format_error("PNG chunk is truncated");
+        }
+        let mut crc = [0u8; 4];
+        reader.read_exact(&mut crc)?;
+        if crc32(&chunk) != u32::from_be_bytes(crc) {
+            return format_error("PNG chunk checksum mismatch");
+        }
+
+        let (kind, data) = chunk.split_at(4);
+        match kind {
+            b"IHDR" => header = Some(Header::parse(data)?),
+            b"PLTE" => {
+                palette = data
+                    .chunks_exact(3)
+                    .map(|rgb| {
+                        Color::new(
+                            srgb_to_linear(f64::from(rgb[0]) / 255.),
+                            srgb_to_linear(f64::from(rgb[1]) / 255.),
+                            srgb_to_linear(f64::from(rgb[2]) / 255.),
+                        )
+                    })
+                    .collect();
+            }
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            // ancillary chunks have a lowercase first letter and can be skipped
+            _ if kind[0].is_ascii_lowercase() => {}
+            _ => {
+                return format_error(format!(
+                    "unsupported critical PNG chunk '{}'",
+                    String::from_utf8_lossy(kind)
+                ))
+            }
+        }
+    }
+
+    let Some(header) = header else {
+        return format_error("PNG file has no IHDR chunk");
+    };
+    if header.color_type == 3 && palette.is_empty() {
+        return format_error("palette PNG has no PLTE chunk");
+    }
+    let data = zlib_decompress(&compressed)?;
+    if data.len() < header.data_len() {
+        return format_error("PNG image data is truncated");
+    }
+
+    let bpp = header.bits_per_pixel().div_ceil(8);
+    let channels = header.channels();
+    let mut image = Framebuffer::new(header.width, header.height);
+    let mut offset = 0;
+    for &(x0, y0, dx, dy) in header.passes() {
+        let width = (header.width + dx - x0 - 1) / dx;
+        let rows = (header.height + dy - y0 - 1) / dy;
+        if width == 0 || rows == 0 {
+            continue;
+        }
+        let row_bytes = header.row_bytes(width);
+        let pixels = unfilter(&data[offset.min(data.len())..], row_bytes, rows, bpp)?;
+        offset += rows * (row_bytes + 1);
+
+        for (j, row) in pixels.chunks_exact(row_bytes).enumerate() {
+            for i in 0..width {
+                let color = if header.color_type == 3 {
+                    let index = usize::from(raw_sample(row, i, header.bit_depth));
+                    match palette.get(index) {
+                        Some(color) => color.clone(),
+                        None => return format_error("PNG palette index out of range"),
+                    }
+                } else {
+                    let value = |c| srgb_to_linear(sample(row, i * channels + c, header.bit_depth));
+                    match header.color_type {
+                        // gray, possibly with alpha
+                        0 | 4 => Color::all(value(0)),
+                        _ => Color::new(value(0), value(1), value(2)),
+                    }
+                };
+                image.set(x0 + i * dx, y0 + j * dy, color);
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// The pixel layouts [`write_png`] can produce. Alpha is always opaque, since a render
+/// covers every pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PngFormat {
+    #[default]
+    Rgb8,
+    Rgba8,
+    Rgb16,
+    Rgba16,
+}
+
+impl FromStr for PngFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rgb8" => Ok(PngFormat::Rgb8),
+            "rgba8" => Ok(PngFormat::Rgba8),
+            "rgb16" => Ok(PngFormat::Rgb16),
+            "rgba16" => Ok(PngFormat::Rgba16),
+            _ => Err(format!(
+                "unknown PNG format '{s}', expected rgb8, rgba8, rgb16 or rgba16"
+            )),
+        }
+    }
+}
+
+impl PngFormat {
+    fn channels(self) -> usize {
+        match self {
+            PngFormat::Rgb8 | PngFormat::Rgb16 => 3,
+            PngFormat::Rgba8 | PngFormat::Rgba16 => 4,
+        }
+    }
+
+    fn bytes_per_sample(self) -> usize {
+        match self {
+            PngFormat::Rgb8 | PngFormat::Rgba8 => 1,
+            PngFormat::Rgb16 | PngFormat::Rgba16 => 2,
+        }
+    }
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut chunk = kind.to_vec();
+    chunk.extend_from_slice(data);
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(&chunk)?;
+    out.write_all(&crc32(&chunk).to_be_bytes())
+}
+
+/// Filters a scanline each of the five ways and keeps whichever leaves the smallest bytes,
+/// the usual guess at what will compress best.
+fn filter_row(row: &[u8], up: Option<&[u8]>, bpp: usize, out: &mut Vec<u8>) {
+    let mut best: Option<(u64, Vec<u8>)> = None;
+    for filter in 0..5u8 {
+        let mut filtered = Vec::with_capacity(row.len() + 1);
+        filtered.push(filter);
+        for x in 0..row.len() {
+            let a = if x >= bpp { row[x - bpp] } else { 0 };
+            let b = up.map_or(0, |up| up[x]);
+            let c = match up {
+                Some(up) if x >= bpp => up[x - bpp],
+                _ => 0,
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                _ => paeth(a, b, c),
+            };
+            filtered.push(row[x].wrapping_sub(predicted));
+        }
+        let cost = filtered[1..]
+            .iter()
+            .map(|&byte| u64::from((byte as i8).unsigned_abs()))
+            .sum();
+        if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+            best = Some((cost, filtered));
+        }
+    }
+    out.extend(best.unwrap().1);
+}
+
+/// Encodes the image as an sRGB PNG, clamping it the same way as [`Framebuffer::write_ppm`].
+pub fn write_png<W: Write>(image: &Framebuffer, mut out: W, format: PngFormat) -> io::Result<()> {
+    let (channels, bytes_per_sample) = (format.channels(), format.bytes_per_sample());
+    let bpp = channels * bytes_per_sample;
+    let row_bytes = image.width() * bpp;
+
+    let mut rows = Vec::with_capacity(row_bytes * image.height());
+    for pixel in image.pixels() {
+        let [r, g, b] = pixel.encoded();
+        let samples = [r, g, b, 1.];
+        for &value in &samples[..channels] {
+            if bytes_per_sample == 2 {
+                rows.extend(((value * 65536.).min(65535.) as u16).to_be_bytes());
+            } else {
+                rows.push((value * 256.).min(255.) as u8);
+            }
+        }
+    }
+
+    let mut filtered = Vec::with_capacity(rows.len() + image.height());
+    let mut up = None;
+    for row in rows.chunks_exact(row_bytes.max(1)) {
+        filter_row(row, up, bpp, &mut filtered);
+        up = Some(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((image.width() as u32).to_be_bytes());
+    header.extend((image.height() as u32).to_be_bytes());
+    let color_type = if channels == 4 { 6 } else { 2 };
+    header.extend([8 * bytes_per_sample as u8, color_type, 0, 0, 0]);
+
+    out.write_all(&PNG_SIGNATURE)?;
+    write_chunk(&mut out, b"IHDR", &header)?;
+    // perceptual rendering intent
+    write_chunk(&mut out, b"sRGB", &[0])?;
+    write_chunk(&mut out, b"IDAT", &zlib_compress(&filtered))?;
+    write_chunk(&mut out, b"IEND", &[])?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// A PNG file made of the given chunks.
+    fn png(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut file = PNG_SIGNATURE.to_vec();
+        for (kind, data) in chunks {
+            write_chunk(&mut file, kind, data).unwrap();
+        }
+        file
+    }
+
+    fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: u8, interlace: u8) -> Vec<u8> {
+        let mut data = width.to_be_bytes().to_vec();
+        data.extend(height.to_be_bytes());
+        data.extend([bit_depth, color_type, 0, 0, interlace]);
+        data
+    }
+
+    /// Prefixes each row with `filter` and applies it, the encoder's side of [`unfilter`].
+    fn filter_rows(rows: &[Vec<u8>], bpp: usize, filter: u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            let up = y.checked_sub(1).map(|y| &rows[y]);
+            out.push(filter);
+            for x in 0..row.len() {
+                let a = if x >= bpp { row[x - bpp] } else { 0 };
+                let b = up.map_or(0, |up| up[x]);
+                let c = match up {
+                    Some(up) if x >= bpp => up[x - bpp],
+                    _ => 0,
+                };
+                let predicted = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                out.push(row[x].wrapping_sub(predicted));
+            }
+        }
+        out
+    }
+
+    /// Decodes a file holding the given header, optional palette and filtered image data.
+    fn decode(header: Vec<u8>, palette: Option<Vec<u8>>, data: &[u8]) -> Framebuffer {
+        let mut chunks = vec![(b"IHDR", header)];
+        chunks.extend(palette.map(|palette| (b"PLTE", palette)));
+        chunks.push((b"IDAT", zlib_compress(data)));
+        chunks.push((b"IEND", Vec::new()));
+        read_png(png(&chunks).as_slice()).unwrap()
+    }
+
+    fn assert_pixels(image: &Framebuffer, expected: impl Fn(usize, usize) -> Color) {
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let (actual, expected) = (image.get(x, y), expected(x, y));
+                assert_eq!(
+                    (actual.r, actual.g, actual.b),
+                    (expected.r, expected.g, expected.b),
+                    "pixel ({x}, {y})"
+                );
+            }
+        }
+    }
+
+    fn gray8(value: u8) -> Color {
+        Color::all(srgb_to_linear(f64::from(value) / 255.))
+    }
+
+    fn error(file: &[u8]) -> String {
+        read_png(file)
+            .expect_err("PNG should be rejected")
+            .to_string()
+    }
+
+    #[test]
+    fn headers_cannot_demand_huge_allocations() {
+        let idat = zlib_compress(&[0, 1, 2, 3]);
+        assert_eq!(
+            error(&png(&[
+                (b"IHDR", ihdr(0x7fff_ffff, 0x7fff_ffff, 8, 2, 0)),
+                (b"IDAT", idat.clone()),
+                (b"IEND", Vec::new()),
+            ])),
+            "PNG image size 2147483647x2147483647 is too large"
+        );
+        assert_eq!(
+            error(&png(&[
+                (b"IHDR", ihdr(8192, 8192, 16, 6, 1)),
+                (b"IDAT", idat),
+                (b"IEND", Vec::new()),
+            ])),
+            "PNG image data is truncated"
+        );
+        assert_eq!(
+            error(&png(&[(b"IHDR", ihdr(0, 4, 8, 2, 0))])),
+            "PNG image is empty"
+        );
+
+        // a chunk claiming 4 GB in a file of a few bytes
+        let mut file = PNG_SIGNATURE.to_vec();
+        file.extend(u32::MAX.to_be_bytes());
+        file.extend(b"IDAT\0\0\0\0");
+        assert_eq!(error(&file), "PNG chunk is truncated");
+    }
+
+    #[test]
+    fn every_filter_type_is_undone() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (width, height) = (5, 4);
+        let rows: Vec<Vec<u8>> = (0..height)
+            .map(|_| (0..3 * width).map(|_| rng.gen()).collect())
+            .collect();
+        for filter in 0..5 {
+            let image = decode(
+                ihdr(width as u32, height as u32, 8, 2, 0),
+                None,
+                &filter_rows(&rows, 3, filter),
+            );
+            assert_pixels(&image, |x, y| {
+                let rgb = &rows[y][3 * x..3 * x + 3];
+                Color::new(gray8(rgb[0]).r, gray8(rgb[1]).r, gray8(rgb[2]).r)
+            });
+        }
+    }
+
+    #[test]
+    fn adam7_passes_are_put_back_in_place() {
+        let mut rng = StdRng::seed_from_u64(2);
+        // the small image leaves some passes empty
+        for (width, height) in [(11, 10), (3, 2)] {
+            let values: Vec<Vec<u8>> = (0..height)
+                .map(|_| (0..width).map(|_| rng.gen()).collect())
+                .collect();
+            let mut data = Vec::new();
+            for (pass, &(x0, y0, dx, dy)) in ADAM7.iter().enumerate() {
+                let rows: Vec<Vec<u8>> = (y0..height)
+                    .step_by(dy)
+                    .map(|y| (x0..width).step_by(dx).map(|x| values[y][x]).collect())
+                    .collect();
+                if rows.first().is_some_and(|row| !row.is_empty()) {
+                    data.extend(filter_rows(&rows, 1, pass as u8 % 5));
+                }
+            }
+            let image = decode(ihdr(width as u32, height as u32, 8, 0, 1), None, &data);
+            assert_pixels(&image, |x, y| gray8(values[y][x]));
+        }
+    }
+
+    #[test]
+    fn packed_palette_indices_are_looked_up() {
+        let palette = vec![0, 0, 0, 255, 0, 0, 0, 128, 0, 10, 20, 30];
+        let indices = [[0, 1, 2, 3, 1], [3, 3, 0, 2, 1], [2, 0, 1, 1, 3]];
+        // four 2-bit indices to a byte, leftmost in the high bits, rows padded to a byte
+        let rows: Vec<Vec<u8>> = indices
+            .iter()
+            .map(|row| {
+                row.chunks(4)
+                    .map(|group| {
+                        group
+                            .iter()
+                            .enumerate()
+                            .map(|(k, &index)| index << (6 - 2 * k))
+                            .sum()
+                    })
+                    .collect()
+            })
+            .collect();
+        let image = decode(
+            ihdr(5, 3, 2, 3, 0),
+            Some(palette.clone()),
+            &filter_rows(&rows, 1, 1),
+        );
+        assert_pixels(&image, |x, y| {
+            let rgb = &palette[3 * indices[y][x] as usize..][..3];
+            Color::new(gray8(rgb[0]).r, gray8(rgb[1]).r, gray8(rgb[2]).r)
+        });
+
+        let file = png(&[
+            (b"IHDR", ihdr(5, 3, 2, 3, 0)),
+            (b"PLTE", palette[..9].to_vec()),
+            (b"IDAT", zlib_compress(&filter_rows(&rows, 1, 0))),
+            (b"IEND", Vec::new()),
+        ]);
+        assert_eq!(error(&file), "PNG palette index out of range");
+    }
+
+    #[test]
+    fn gray_with_alpha_ignores_the_alpha() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let rows: Vec<Vec<u8>> = (0..3)
+            .map(|_| (0..16).map(|_| rng.gen()).collect())
+            .collect();
+
+        // the same bytes hold eight 8-bit pixels or four 16-bit ones per row
+        let image = decode(ihdr(8, 3, 8, 4, 0), None, &filter_rows(&rows, 2, 4));
+        assert_pixels(&image, |x, y| gray8(rows[y][2 * x]));
+
+        let image = decode(ihdr(4, 3, 16, 4, 0), None, &filter_rows(&rows, 4, 3));
+        assert_pixels(&image, |x, y| {
+            let gray = u16::from_be_bytes([rows[y][4 * x], rows[y][4 * x + 1]]);
+            Color::all(srgb_to_linear(f64::from(gray) / 65535.))
+        });
+    }
+
+    #[test]
+    fn written_images_read_back_in_every_format() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let (width, height) = (300, 7);
+        // a gradient, so the filters have something to predict, plus noise and values outside
+        // [0, 1] to be clamped
+        let pixels = (0..width * height)
+            .map(|i| {
+                let t = (i % width) as f64 / width as f64;
+                Color::new(t, rng.gen_range(-0.5..1.5), 1. - t)
+            })
+            .collect();
+        let image = Framebuffer::from_pixels(width, height, pixels);
+
+        for format in [
+            PngFormat::Rgb8,
+            PngFormat::Rgba8,
+            PngFormat::Rgb16,
+            PngFormat::Rgba16,
+        ] {
+            let mut file = Vec::new();
+            write_png(&image, &mut file, format).unwrap();
+            let decoded = read_png(file.as_slice()).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (width, height));
+            assert_pixels(&decoded, |x, y| {
+                let [r, g, b] = image.get(x, y).encoded().map(|value| {
+                    if format.bytes_per_sample() == 2 {
+                        f64::from((value * 65536.).min(65535.) as u16) / 65535.
+                    } else {
+                        f64::from((value * 256.).min(255.) as u8) / 255.
+                    }
+                });
+                Color::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b))
+            });
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{rand, rand_range, Color};
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::ops::{Add, Div, Index, Mul, Neg, Sub};
 
 #[derive(Debug, Copy, Clone, Default)]
 pub struct Point {
@@ -75,6 +75,15 @@ impl Point {
         }
     }
 
+    /// Cosine-distributed direction in the hemisphere around +z.
+    #[must_use]
+    pub fn random_cosine_direction() -> Self {
+        let phi = 2. * std::f64::consts::PI * rand();
+        let r2 = rand();
+        let r = r2.sqrt();
+        Self::new(r * phi.cos(), r * phi.sin(), (1. - r2).sqrt())
+    }
+
     #[must_use]
     pub fn random_unit_vector() -> Self {
         Point::random_in_unit_sphere().unit()
@@ -102,6 +111,23 @@ impl Point {
         *self / self.len()
     }
 
+    pub fn min(&self, rhs: Point) -> Self {
+        Self::new(self.x.min(rhs.x), self.y.min(rhs.y), self.z.min(rhs.z))
+    }
+    pub fn max(&self, rhs: Point) -> Self {
+        Self::new(self.x.max(rhs.x), self.y.max(rhs.y), self.z.max(rhs.z))
+    }
+
+    /// Rotates counter-clockwise around the y axis when looking down from +y.
+    pub fn rotate_y(&self, angle: f64) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::new(
+            cos * self.x + sin * self.z,
+            self.y,
+            -sin * self.x + cos * self.z,
+        )
+    }
+
     pub fn is_near_zero(&self) -> bool {
         const EPSILON: f64 = 0.0000001;
         self.x.abs() < EPSILON && self.y.abs() < EPSILON && self.z.abs() < EPSILON
@@ -180,6 +206,18 @@ impl Div<Point> for f64 {
     }
 }
 
+impl Index<usize> for Point {
+    type Output = f64;
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("point axis out of range: {axis}"),
+        }
+    }
+}
+
 impl From<Color> for Point {
     fn from(c: Color) -> Self {
         Self::new(c.r, c.g, c.b)
@@ -1,8 +1,8 @@
+use crate::color::*;
 use crate::point::*;
 use crate::Collidable;
 use crate::Material;
-use crate::{color::*, ScatterOutcome, ScatterResult};
-use crate::{MAX_DEPTH, WORLD};
+use crate::Scene;
 use std::cmp::Ordering::*;
 use std::sync::Arc;
 
@@ -19,6 +19,9 @@ pub struct Collision {
     pub dist: f64,
     pub facing: Facing,
     pub material: Arc<dyn Material>,
+    pub uv: (f64, f64),
+    /// Position of the hit object in the list the scene was built from.
+    pub object_id: usize,
 }
 
 impl Collision {
@@ -35,9 +38,16 @@ impl Collision {
             dist,
             facing,
             material,
+            uv: (0., 0.),
+            object_id: 0,
         }
     }
 
+    #[must_use]
+    pub fn with_uv(self, u: f64, v: f64) -> Self {
+        Collision { uv: (u, v), ..self }
+    }
+
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
         match ray.direction.dot_product(outward_normal).total_cmp(&0.) {
             Less => {
@@ -70,36 +80,39 @@ impl Ray {
         other.collide(self, t_min, t_max)
     }
 
-    fn do_color(&self, depth: i32) -> Color {
+    fn do_color(&self, scene: &Scene, depth: i32) -> Color {
         if depth <= 0 {
             return Color::black();
         }
+        self.shade(
+            scene.collide(self, 0.001, f64::INFINITY).as_ref(),
+            scene,
+            depth,
+        )
+    }
 
-        let world_guard = WORLD.read().unwrap();
+    /// Like [`Ray::color`], given what the ray hits first.
+    pub fn shade(&self, hit: Option<&Collision>, scene: &Scene, depth: i32) -> Color {
+        if depth <= 0 {
+            return Color::black();
+        }
 
-        match world_guard.collide(self, 0.001, f64::INFINITY) {
+        match hit {
             Some(collision) => {
-                let ScatterResult {
-                    outcome,
-                    attenuation,
-                    scattered_ray,
-                } = collision.material.scatter(self, &collision);
-
-                drop(world_guard);
-
-                match outcome {
-                    ScatterOutcome::Scattered => attenuation * scattered_ray.do_color(depth - 1),
-                    ScatterOutcome::Absorbed => Color::black(),
+                let emitted = collision.material.emitted(collision);
+                match collision.material.sample(-self.direction, collision) {
+                    Some(sample) => {
+                        let scattered = Ray::new(collision.point, sample.direction);
+                        emitted + sample.weight * scattered.do_color(scene, depth - 1)
+                    }
+                    None => emitted,
                 }
             }
-            None => {
-                let t = 0.5 * (self.direction.unit().y + 1.);
-                Color::from((1. - t) * Point::all(1.) + t * Point::new(0.5, 0.7, 1.))
-            }
+            None => scene.background.color(self.direction),
         }
     }
 
-    pub fn color(&self) -> Color {
-        self.do_color(MAX_DEPTH)
+    pub fn color(&self, scene: &Scene, max_depth: i32) -> Color {
+        self.do_color(scene, max_depth)
     }
 }
@@ -0,0 +1,436 @@
+use crate::{
+    rand, seed_rng, tiles, AovPixel, Aovs, Camera, Color, Framebuffer, Integrator, Scene, Tile,
+    TileOrder,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    /// Samples per pixel, or with adaptive sampling the most any pixel may take.
+    pub samples: u32,
+    /// With adaptive sampling, the fewest samples a pixel takes before it may stop.
+    pub min_samples: u32,
+    /// Turns on adaptive sampling: a pixel stops once the standard error of its mean
+    /// luminance falls below this fraction of the luminance.
+    pub adaptive_threshold: Option<f64>,
+    pub max_depth: i32,
+    pub threads: usize,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub seed: Option<u64>,
+    pub integrator: Integrator,
+    /// Whether to also record the first hits behind each pixel.
+    pub aovs: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 800,
+            samples: 64,
+            min_samples: 16,
+            adaptive_threshold: None,
+            max_depth: 50,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 16,
+            tile_order: TileOrder::default(),
+            seed: None,
+            integrator: Integrator::default(),
+            aovs: false,
+        }
+    }
+}
+
+impl RenderSettings {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn width(self, width: usize) -> Self {
+        RenderSettings { width, ..self }
+    }
+
+    #[must_use]
+    pub fn height(self, height: usize) -> Self {
+        RenderSettings { height, ..self }
+    }
+
+    #[must_use]
+    pub fn samples(self, samples: u32) -> Self {
+        RenderSettings { samples, ..self }
+    }
+
+    #[must_use]
+    pub fn min_samples(self, min_samples: u32) -> Self {
+        RenderSettings {
+            min_samples,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn adaptive_threshold(self, threshold: f64) -> Self {
+        RenderSettings {
+            adaptive_threshold: Some(threshold),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn max_depth(self, max_depth: i32) -> Self {
+        RenderSettings { max_depth, ..self }
+    }
+
+    #[must_use]
+    pub fn threads(self, threads: usize) -> Self {
+        RenderSettings { threads, ..self }
+    }
+
+    #[must_use]
+    pub fn tile_size(self, tile_size: usize) -> Self {
+        RenderSettings { tile_size, ..self }
+    }
+
+    #[must_use]
+    pub fn tile_order(self, tile_order: TileOrder) -> Self {
+        RenderSettings { tile_order, ..self }
+    }
+
+    /// Makes the image reproducible, independently of the thread count and tiling.
+    #[must_use]
+    pub fn seed(self, seed: u64) -> Self {
+        RenderSettings {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn integrator(self, integrator: Integrator) -> Self {
+        RenderSettings { integrator, ..self }
+    }
+
+    #[must_use]
+    pub fn aovs(self, aovs: bool) -> Self {
+        RenderSettings { aovs, ..self }
+    }
+}
+
+/// The beauty image, plus the AOVs when the settings ask for them.
+pub struct RenderOutput {
+    pub image: Framebuffer,
+    pub aovs: Option<Aovs>,
+}
+
+type ProgressFn = Box<dyn Fn(f64) + Send + Sync>;
+
+pub struct Renderer {
+    settings: RenderSettings,
+    progress: Option<ProgressFn>,
+}
+
+impl Renderer {
+    /// Panics if the settings ask for no samples, which would leave every pixel undefined.
+    #[must_use]
+    pub fn new(settings: RenderSettings) -> Self {
+        assert!(
+            settings.samples > 0,
+            "a render needs at least one sample per pixel"
+        );
+        Self {
+            settings,
+            progress: None,
+        }
+    }
+
+    /// Called from the worker threads with the finished fraction after every completed tile.
+    #[must_use]
+    pub fn on_progress(self, progress: impl Fn(f64) + Send + Sync + 'static) -> Self {
+        Renderer {
+            progress: Some(Box::new(progress)),
+            ..self
+        }
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    pub fn render(&self, scene: &Scene) -> Framebuffer {
+        self.render_output(scene).image
+    }
+
+    pub fn render_output(&self, scene: &Scene) -> RenderOutput {
+        let RenderSettings {
+            width,
+            height,
+            threads,
+            tile_size,
+            tile_order,
+            aovs,
+            ..
+        } = self.settings;
+        if width == 0 || height == 0 {
+            return RenderOutput {
+                image: Framebuffer::new(width, height),
+                aovs: aovs.then(|| Aovs::new(width, height, Vec::new())),
+            };
+        }
+
+        // the frame's shape, not the scene's camera, decides the aspect ratio
+        let camera = scene
+            .camera
+            .clone()
+            .aspect_ratio(width as f64 / height as f64);
+        let tiles = tiles(width, height, tile_size, tile_order);
+        let framebuffer = Mutex::new(Framebuffer::new(width, height));
+        let aov_pixels = Mutex::new(vec![
+            AovPixel::default();
+            if aovs { width * height } else { 0 }
+        ]);
+        let next_tile = AtomicUsize::new(0);
+        let pixels_done = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..threads.clamp(1, tiles.len()) {
+                s.spawn(|| {
+                    // workers keep pulling the next unclaimed tile until none are left
+                    while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                        let (pixels, tile_aovs) = self.render_tile(scene, &camera, tile);
+                        framebuffer.lock().unwrap().write_tile(tile, &pixels);
+                        if aovs {
+                            let mut aov_pixels = aov_pixels.lock().unwrap();
+                            for (row, source) in tile_aovs.chunks(tile.width).enumerate() {
+                                let start = (tile.y + row) * width + tile.x;
+                                aov_pixels[start..start + tile.width].clone_from_slice(source);
+                            }
+                        }
+
+                        let done = pixels_done.fetch_add(tile.pixel_count(), Ordering::Relaxed)
+                            + tile.pixel_count();
+                        if let Some(progress) = &self.progress {
+                            progress(done as f64 / (width * height) as f64);
+                        }
+                    }
+                });
+            }
+        });
+
+        RenderOutput {
+            image: framebuffer.into_inner().unwrap(),
+            aovs: aovs.then(|| Aovs::new(width, height, aov_pixels.into_inner().unwrap())),
+        }
+    }
+
+    /// The tile's colors, and its AOVs if they are wanted, row by row.
+    fn render_tile(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        tile: &Tile,
+    ) -> (Vec<Color>, Vec<AovPixel>) {
+        let mut pixels = Vec::with_capacity(tile.pixel_count());
+        let mut aovs = Vec::new();
+        for y in tile.y..tile.y + tile.height {
+            for x in tile.x..tile.x + tile.width {
+                if let Some(seed) = self.settings.seed {
+                    let index = (y * self.settings.width + x) as u64;
+                    seed_rng(seed ^ (index + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+                }
+                let mut aov = AovPixel::default();
+                let aov_slot = self.settings.aovs.then_some(&mut aov);
+                pixels.push(self.render_pixel(scene, camera, x, y, aov_slot));
+                if self.settings.aovs {
+                    aovs.push(aov);
+                }
+            }
+        }
+        (pixels, aovs)
+    }
+
+    fn render_pixel(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        x: usize,
+        y: usize,
+        mut aov: Option<&mut AovPixel>,
+    ) -> Color {
+        let RenderSettings {
+            width,
+            height,
+            samples,
+            min_samples,
+            adaptive_threshold,
+            max_depth,
+            integrator,
+            ..
+        } = self.settings;
+
+        let mut color = Color::black();
+        let mut stats = PixelStats::default();
+        // a variance needs at least two samples
+        let min_samples = min_samples.clamp(2, samples.max(2));
+        while stats.count < samples {
+            // image rows run top to bottom while the camera's y axis points up
+            let u = (x as f64 + rand()) / width as f64;
+            let v = ((height - 1 - y) as f64 + rand()) / height as f64;
+            let ray = camera.get_ray(u, v);
+            let (sample, hit) = integrator.radiance(&ray, scene, max_depth);
+            if let Some(aov) = aov.as_deref_mut() {
+                aov.add(&ray, hit.as_ref(), scene);
+            }
+            stats.add(sample.luminance());
+            color = color + sample;
+            if let Some(threshold) = adaptive_threshold {
+                if stats.count >= min_samples && stats.relative_error() < threshold {
+                    break;
+                }
+            }
+        }
+        color / f64::from(stats.count)
+    }
+}
+
+/// Luminances below this count as this much when judging a pixel's relative error, so
+/// noise too dark to see doesn't take every sample there is.
+const DARK_LUMINANCE: f64 = 0.05;
+
+/// Running mean and variance of a pixel's sample luminances (Welford's algorithm).
+#[derive(Default)]
+struct PixelStats {
+    count: u32,
+    mean: f64,
+    /// Sum of squared differences from the mean.
+    m2: f64,
+}
+
+impl PixelStats {
+    fn add(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / f64::from(self.count);
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// Standard error of the mean, relative to the mean.
+    fn relative_error(&self) -> f64 {
+        let n = f64::from(self.count);
+        let variance = self.m2 / (n - 1.);
+        (variance / n).sqrt() / self.mean.max(DARK_LUMINANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CollidableVec, Lambertian, Point, SolidColor, Sphere};
+
+    fn black_sphere_on_white(camera: Camera) -> Scene {
+        let objects: CollidableVec = vec![Sphere::boxed(
+            Point::origin(),
+            1.,
+            Lambertian::new_arc(Color::black()),
+        )];
+        Scene::new(objects, camera).background(SolidColor::new_arc(Color::white()))
+    }
+
+    fn render(scene: &Scene, width: usize, height: usize) -> Framebuffer {
+        let settings = RenderSettings::new()
+            .width(width)
+            .height(height)
+            .samples(4)
+            .threads(3)
+            .seed(1);
+        Renderer::new(settings).render(scene)
+    }
+
+    #[test]
+    fn odd_sizes_fill_every_pixel() {
+        let camera = Camera::new()
+            .look_from(Point::new(0., 0., 5.))
+            .look_at(Point::origin())
+            .lens_radius(0.);
+        let scene = black_sphere_on_white(camera.vfov(90.));
+
+        for (width, height) in [(1, 1), (37, 23), (5, 64), (101, 7)] {
+            let framebuffer = render(&scene, width, height);
+            assert_eq!(framebuffer.width(), width);
+            assert_eq!(framebuffer.height(), height);
+            assert_eq!(framebuffer.pixels().len(), width * height);
+            if width > 1 && height > 1 {
+                assert!(framebuffer.get(width / 2, height / 2).r < 0.01);
+                assert!(framebuffer.get(0, 0).r > 0.99);
+                assert!(framebuffer.get(width - 1, height - 1).r > 0.99);
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_spends_samples_on_edges() {
+        let camera = Camera::new()
+            .look_from(Point::new(0., 0., 5.))
+            .look_at(Point::origin())
+            .vfov(60.)
+            .lens_radius(0.);
+        let scene = black_sphere_on_white(camera);
+        let settings = RenderSettings::new()
+            .width(40)
+            .height(40)
+            .samples(256)
+            .min_samples(8)
+            .adaptive_threshold(0.05)
+            .threads(3)
+            .seed(1)
+            .aovs(true);
+        let aovs = Renderer::new(settings).render_output(&scene).aovs.unwrap();
+        let samples = |x: usize, y: usize| aovs.pixels()[y * 40 + x].samples();
+
+        // flat background and the sphere's black middle settle right away
+        assert_eq!(samples(0, 0), 8);
+        assert_eq!(samples(20, 20), 8);
+        let most = (0..40 * 40).map(|i| samples(i % 40, i / 40)).max().unwrap();
+        assert!(most > 64, "edge pixels took at most {most} samples");
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one sample")]
+    fn zero_samples_are_rejected() {
+        let _ = Renderer::new(RenderSettings::new().samples(0));
+    }
+
+    #[test]
+    fn non_square_frames_are_not_stretched() {
+        let camera = Camera::new()
+            .look_from(Point::new(0., 0., 5.))
+            .look_at(Point::origin())
+            .vfov(60.)
+            .lens_radius(0.);
+        let scene = black_sphere_on_white(camera);
+
+        for (width, height) in [(120, 50), (50, 120), (81, 81)] {
+            let framebuffer = render(&scene, width, height);
+            let covered = |pixels: &mut dyn Iterator<Item = &Color>| {
+                pixels.filter(|color| color.r < 0.5).count() as f64
+            };
+            let across = covered(&mut (0..width).map(|x| framebuffer.get(x, height / 2)));
+            let down = covered(&mut (0..height).map(|y| framebuffer.get(width / 2, y)));
+
+            assert!(
+                across > 10.,
+                "sphere should be visible in a {width}x{height} frame"
+            );
+            assert!(
+                (across - down).abs() <= 2.,
+                "sphere is {across} pixels wide but {down} tall in a {width}x{height} frame"
+            );
+        }
+    }
+}
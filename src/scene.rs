@@ -0,0 +1,59 @@
+use crate::{
+    Background, Bvh, Camera, Collidable, CollidableVec, Collision, Emitter, Gradient, Material, Ray,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Tells materials apart by their address.
+fn material_key(material: &Arc<dyn Material>) -> usize {
+    Arc::as_ptr(material) as *const () as usize
+}
+
+/// Everything needed to trace a frame; immutable once built, so threads share it freely.
+pub struct Scene {
+    pub objects: Bvh,
+    /// Emissive surfaces gathered from `objects` when the scene is built.
+    pub lights: Vec<Box<dyn Emitter>>,
+    pub background: Arc<dyn Background>,
+    pub camera: Camera,
+    material_ids: HashMap<usize, usize>,
+}
+
+impl Scene {
+    #[must_use]
+    pub fn new(objects: CollidableVec, camera: Camera) -> Self {
+        let mut material_ids = HashMap::new();
+        for material in objects.materials() {
+            let next = material_ids.len() + 1;
+            material_ids.entry(material_key(&material)).or_insert(next);
+        }
+        let objects = Bvh::new(objects);
+        Self {
+            lights: objects.emitters(),
+            objects,
+            background: Arc::new(Gradient::default()),
+            camera,
+            material_ids,
+        }
+    }
+
+    #[must_use]
+    pub fn background(self, background: Arc<dyn Background>) -> Self {
+        Scene { background, ..self }
+    }
+
+    /// Emissive surfaces plus the background when it can be sampled.
+    pub fn light_count(&self) -> usize {
+        self.lights.len() + usize::from(self.background.is_light())
+    }
+
+    /// Materials numbered from 1 in the order the objects using them were listed, so the
+    /// numbers stay the same whatever part of the scene is rendered.
+    pub fn material_id(&self, material: &Arc<dyn Material>) -> Option<usize> {
+        self.material_ids.get(&material_key(material)).copied()
+    }
+
+    pub fn collide(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Collision> {
+        self.objects.collide(ray, t_min, t_max)
+    }
+}
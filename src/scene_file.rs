@@ -0,0 +1,898 @@
+//! A small block-structured text format describing a whole render:
+//!
+//! ```text
+//! # comments run to the end of the line
+//! camera {
+//!     look_from 8.2 4.2 3
+//!     look_at 0 0 0
+//!     vup 0 1 0
+//!     vfov 20
+//!     lens_radius 0.02
+//!     focus_dist 10
+//! }
+//!
+//! render { width 800 height 600 samples 64 max_depth 50 }
+//! background gradient { bottom 1 1 1 top 0.5 0.7 1 }   # or: background solid { color 0 0 0 }
+//! # or an equirectangular panorama, rotated in degrees about the vertical axis:
+//! # background latlong { file "studio.hdr" rotation 90 intensity 1.5 }
+//!
+//! # textures can stand in for any material color, by name
+//! texture tiles checker { even 0.9 0.9 0.9 odd 0.1 0.1 0.1 frequency 20 }  # or: size 0.5
+//! texture wood image { file "wood.png" wrap mirror }    # or: texture red solid { color 1 0 0 }
+//! # images may be PPM, PFM, Radiance HDR or PNG; wrap is repeat (the default), clamp or mirror
+//! # 3D noise: perlin, fbm, turbulence, worley, or the marble and wood presets
+//! texture veins noise { pattern marble seed 7 scale 4 octaves 7 low 0.1 0.1 0.1 high 1 1 1 }
+//!
+//! material ground lambertian { albedo tiles }
+//! material chrome metal { albedo 0.6 0.6 0.6 fuzz 0.15 }
+//! material glass dielectric { ior 1.5 }
+//! material brushed conductor { preset gold roughness 0.3 }   # or: eta 0.2 0.9 1.1 k 3.9 2.5 2.1
+//! material frosted rough_dielectric { ior 1.5 roughness 0.2 }
+//! material paint principled {
+//!     base_color 0.8 0.1 0.1 metallic 0 roughness 0.4 specular 0.5
+//!     clearcoat 1 sheen 0 transmission 0 ior 1.5
+//! }
+//! material lamp diffuse_light { emit 4 4 4 }
+//!
+//! sphere { center 0 -1000 0 radius 1000 material ground }
+//! triangle { a 0 0 0 b 1 0 0 c 0 1 0 material chrome }
+//! mesh { file "models/teapot.obj" }
+//! ```
+//!
+//! Textures must be declared before the materials that use them, and materials before the
+//! objects that use them. Every key inside a block is optional unless the object cannot
+//! exist without it (a sphere's `radius`, a mesh's `file`). Mesh paths are relative to the
+//! scene file and take their materials from the OBJ's own MTL libraries unless a
+//! `material` overrides them all.
+
+use crate::{
+    load_obj, Background, Camera, Checker, CollidableVec, Color, Conductor, Dielectric,
+    DiffuseLight, Gradient, ImageError, ImageTexture, Lambertian, LatLongMap, Material, Metal,
+    NoisePattern, NoiseTexture, ObjError, Point, Principled, RoughDielectric, Scene, SolidColor,
+    Sphere, Texture, Triangle, TriangleMesh, WrapMode,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Checked as soon as the type is read, so a misspelt type is reported before its keys are.
+const MATERIAL_TYPES: [&str; 7] = [
+    "lambertian",
+    "metal",
+    "dielectric",
+    "rough_dielectric",
+    "conductor",
+    "principled",
+    "diffuse_light",
+];
+const BACKGROUND_TYPES: [&str; 3] = ["solid", "gradient", "latlong"];
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, std::io::Error),
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Obj {
+        line: usize,
+        column: usize,
+        source: ObjError,
+    },
+    Image {
+        line: usize,
+        column: usize,
+        source: ImageError,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            SceneError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "{line}:{column}: {message}"),
+            SceneError::Obj {
+                line,
+                column,
+                source,
+            } => write!(f, "{line}:{column}: failed to load mesh: {source}"),
+            SceneError::Image {
+                line,
+                column,
+                source,
+            } => write!(f, "{line}:{column}: failed to load image: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io(_, err) => Some(err),
+            SceneError::Parse { .. } => None,
+            SceneError::Obj { source, .. } => Some(source),
+            SceneError::Image { source, .. } => Some(source),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SceneSettings {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples: Option<u32>,
+    pub max_depth: Option<i32>,
+}
+
+pub struct SceneDescription {
+    pub scene: Scene,
+    pub settings: SceneSettings,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Str(String),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Word(word) => write!(f, "'{word}'"),
+            TokenKind::Str(s) => write!(f, "\"{s}\""),
+            TokenKind::Open => write!(f, "'{{'"),
+            TokenKind::Close => write!(f, "'}}'"),
+        }
+    }
+}
+
+fn parse_error(line: usize, column: usize, message: impl Into<String>) -> SceneError {
+    SceneError::Parse {
+        line,
+        column,
+        message: message.into(),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, SceneError> {
+    let mut tokens = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_no = line_index + 1;
+        let mut chars = line.char_indices().peekable();
+
+        while let Some((start, c)) = chars.next() {
+            let column = line[..start].chars().count() + 1;
+            let kind = match c {
+                '#' => break,
+                c if c.is_whitespace() => continue,
+                '{' => TokenKind::Open,
+                '}' => TokenKind::Close,
+                '"' => {
+                    let mut s = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, c)) => s.push(c),
+                            None => {
+                                return Err(parse_error(line_no, column, "unterminated string"))
+                            }
+                        }
+                    }
+                    TokenKind::Str(s)
+                }
+                c => {
+                    let mut word = c.to_string();
+                    while let Some(&(_, c)) = chars.peek() {
+                        if c.is_whitespace() || matches!(c, '{' | '}' | '"' | '#') {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+                    TokenKind::Word(word)
+                }
+            };
+            tokens.push(Token {
+                kind,
+                line: line_no,
+                column,
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    base_dir: PathBuf,
+    materials: HashMap<String, Arc<dyn Material>>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    end: (usize, usize),
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, SceneError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => Err(parse_error(
+                self.end.0,
+                self.end.1,
+                format!("expected {expected}, found end of file"),
+            )),
+        }
+    }
+
+    fn word(&mut self, expected: &str) -> Result<(String, Token), SceneError> {
+        let token = self.next(expected)?;
+        match &token.kind {
+            TokenKind::Word(word) => Ok((word.clone(), token)),
+            other => Err(parse_error(
+                token.line,
+                token.column,
+                format!("expected {expected}, found {other}"),
+            )),
+        }
+    }
+
+    fn string(&mut self, expected: &str) -> Result<String, SceneError> {
+        let token = self.next(expected)?;
+        match token.kind {
+            TokenKind::Str(s) | TokenKind::Word(s) => Ok(s),
+            other => Err(parse_error(
+                token.line,
+                token.column,
+                format!("expected {expected}, found {other}"),
+            )),
+        }
+    }
+
+    fn open(&mut self) -> Result<Token, SceneError> {
+        let token = self.next("'{'")?;
+        match token.kind {
+            TokenKind::Open => Ok(token),
+            ref other => Err(parse_error(
+                token.line,
+                token.column,
+                format!("expected '{{', found {other}"),
+            )),
+        }
+    }
+
+    /// Returns the next key of the current block, or `None` after consuming its `}`.
+    fn key(&mut self) -> Result<Option<(String, Token)>, SceneError> {
+        if let Some(Token {
+            kind: TokenKind::Close,
+            ..
+        }) = self.peek()
+        {
+            self.pos += 1;
+            return Ok(None);
+        }
+        self.word("a key or '}'").map(Some)
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, what: &str) -> Result<T, SceneError> {
+        let (word, token) = self.word(what)?;
+        word.parse().map_err(|_| {
+            parse_error(
+                token.line,
+                token.column,
+                format!("expected {what}, found '{word}'"),
+            )
+        })
+    }
+
+    fn positive<T: std::str::FromStr + Default + PartialOrd>(&mut self) -> Result<T, SceneError> {
+        let value = self.number("a positive integer")?;
+        if value > T::default() {
+            Ok(value)
+        } else {
+            let token = &self.tokens[self.pos - 1];
+            Err(parse_error(
+                token.line,
+                token.column,
+                format!("expected a positive integer, found {}", token.kind),
+            ))
+        }
+    }
+
+    fn float(&mut self) -> Result<f64, SceneError> {
+        self.number("a number")
+    }
+
+    fn point(&mut self) -> Result<Point, SceneError> {
+        Ok(Point::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    fn color(&mut self) -> Result<Color, SceneError> {
+        Ok(Color::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    /// A color slot: either an inline color or the name of a texture declared earlier.
+    fn texture(&mut self) -> Result<Arc<dyn Texture>, SceneError> {
+        if let Some(Token {
+            kind: TokenKind::Word(word),
+            line,
+            column,
+        }) = self.peek()
+        {
+            if word.parse::<f64>().is_err() {
+                let texture = self.textures.get(word).cloned().ok_or_else(|| {
+                    parse_error(*line, *column, format!("unknown texture '{word}'"))
+                })?;
+                self.pos += 1;
+                return Ok(texture);
+            }
+        }
+        Ok(Arc::new(self.color()?))
+    }
+
+    fn material_ref(&mut self) -> Result<Arc<dyn Material>, SceneError> {
+        let (name, token) = self.word("a material name")?;
+        self.materials.get(&name).cloned().ok_or_else(|| {
+            parse_error(
+                token.line,
+                token.column,
+                format!("unknown material '{name}'"),
+            )
+        })
+    }
+
+    fn unknown_key(key: &str, token: &Token, block: &str) -> SceneError {
+        parse_error(
+            token.line,
+            token.column,
+            format!("unknown key '{key}' in {block} block"),
+        )
+    }
+
+    fn missing(block: &Token, what: &str, key: &str) -> SceneError {
+        parse_error(
+            block.line,
+            block.column,
+            format!("{what} is missing required key '{key}'"),
+        )
+    }
+
+    fn camera(&mut self, mut camera: Camera) -> Result<Camera, SceneError> {
+        self.open()?;
+        while let Some((key, token)) = self.key()? {
+            camera = match key.as_str() {
+                "look_from" => camera.look_from(self.point()?),
+                "look_at" => camera.look_at(self.point()?),
+                "vup" => camera.vup(self.point()?),
+                "vfov" => camera.vfov(self.float()?),
+                "lens_radius" => camera.lens_radius(self.float()?),
+                "focus_dist" => camera.focus_dist(self.float()?),
+                _ => return Err(Self::unknown_key(&key, &token, "camera")),
+            };
+        }
+        Ok(camera)
+    }
+
+    fn settings(&mut self, mut settings: SceneSettings) -> Result<SceneSettings, SceneError> {
+        self.open()?;
+        while let Some((key, token)) = self.key()? {
+            match key.as_str() {
+                "width" => settings.width = Some(self.positive()?),
+                "height" => settings.height = Some(self.positive()?),
+                "samples" => settings.samples = Some(self.positive()?),
+                "max_depth" => settings.max_depth = Some(self.positive()?),
+                _ => return Err(Self::unknown_key(&key, &token, "render")),
+            }
+        }
+        Ok(settings)
+    }
+
+    fn background(&mut self) -> Result<Arc<dyn Background>, SceneError> {
+        let (kind, kind_token) = self.word("a background type")?;
+        if !BACKGROUND_TYPES.contains(&kind.as_str()) {
+            return Err(parse_error(
+                kind_token.line,
+                kind_token.column,
+                format!("unknown background type '{kind}'"),
+            ));
+        }
+        let mut color = Color::black();
+        let mut gradient = Gradient::default();
+        let mut file = None;
+        let mut rotation = 0.;
+        let mut intensity = 1.;
+
+        let block = self.open()?;
+        while let Some((key, token)) = self.key()? {
+            match (kind.as_str(), key.as_str()) {
+                ("solid", "color") => color = self.color()?,
+                ("gradient", "bottom") => gradient = Gradient::new(self.color()?, gradient.top),
+                ("gradient", "top") => gradient = Gradient::new(gradient.bottom, self.color()?),
+                ("latlong", "file") => file = Some((self.string("a file path")?, token)),
+                ("latlong", "rotation") => rotation = self.float()?,
+                ("latlong", "intensity") => intensity = self.float()?,
+                _ => return Err(Self::unknown_key(&key, &token, &kind)),
+            }
+        }
+
+        match kind.as_str() {
+            "solid" => Ok(SolidColor::new_arc(color)),
+            "gradient" => Ok(Arc::new(gradient)),
+            "latlong" => {
+                let (file, token) =
+                    file.ok_or_else(|| Self::missing(&block, "latlong background", "file"))?;
+                let map = LatLongMap::load(self.base_dir.join(file)).map_err(|source| {
+                    SceneError::Image {
+                        line: token.line,
+                        column: token.column,
+                        source,
+                    }
+                })?;
+                Ok(Arc::new(map.rotation(rotation).intensity(intensity)))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn texture_block(&mut self) -> Result<(), SceneError> {
+        let (name, name_token) = self.word("a texture name")?;
+        if self.textures.contains_key(&name) {
+            return Err(parse_error(
+                name_token.line,
+                name_token.column,
+                format!("texture '{name}' is already defined"),
+            ));
+        }
+
+        let (kind, kind_token) = self.word("a texture type")?;
+        let block = self.open()?;
+        let mut color: Arc<dyn Texture> = Arc::new(Color::all(0.5));
+        let mut even: Arc<dyn Texture> = Arc::new(Color::white());
+        let mut odd: Arc<dyn Texture> = Arc::new(Color::black());
+        let mut frequency = 10.;
+        let mut size = None;
+        let mut file = None;
+        let mut wrap = WrapMode::Repeat;
+        let mut pattern = None;
+        let mut seed = 0;
+        let mut scale = 1.;
+        let mut octaves = None;
+        let (mut low, mut high) = (None, None);
+
+        while let Some((key, token)) = self.key()? {
+            match (kind.as_str(), key.as_str()) {
+                ("solid", "color") => color = self.texture()?,
+                ("noise", "pattern") => pattern = Some(self.word("a noise pattern")?),
+                ("noise", "seed") => seed = self.number("a non-negative integer")?,
+                ("noise", "scale") => scale = self.float()?,
+                ("noise", "octaves") => octaves = Some(self.positive()?),
+                ("noise", "low") => low = Some(self.texture()?),
+                ("noise", "high") => high = Some(self.texture()?),
+                ("checker", "even") => even = self.texture()?,
+                ("checker", "odd") => odd = self.texture()?,
+                ("checker", "frequency") => frequency = self.float()?,
+                ("checker", "size") => size = Some(self.float()?),
+                ("image", "file") => file = Some((self.string("a file path")?, token)),
+                ("image", "wrap") => {
+                    let (mode, token) = self.word("a wrap mode")?;
+                    wrap = mode
+                        .parse()
+                        .map_err(|message| parse_error(token.line, token.column, message))?;
+                }
+                _ => return Err(Self::unknown_key(&key, &token, &kind)),
+            }
+        }
+
+        let texture: Arc<dyn Texture> = match kind.as_str() {
+            "solid" => color,
+            // a cube size switches the checker from the surface's UVs to world space
+            "checker" => match size {
+                Some(size) => Arc::new(Checker::world(even, odd, size)),
+                None => Arc::new(Checker::uv(even, odd, frequency)),
+            },
+            "image" => {
+                let (file, token) =
+                    file.ok_or_else(|| Self::missing(&block, "image texture", "file"))?;
+                let image = ImageTexture::load(self.base_dir.join(file)).map_err(|source| {
+                    SceneError::Image {
+                        line: token.line,
+                        column: token.column,
+                        source,
+                    }
+                })?;
+                Arc::new(image.wrap(wrap))
+            }
+            "noise" => {
+                let (name, token) =
+                    pattern.ok_or_else(|| Self::missing(&block, "noise texture", "pattern"))?;
+                let pattern = match name.as_str() {
+                    "perlin" => NoisePattern::Perlin,
+                    "fbm" => NoisePattern::Fbm {
+                        octaves: octaves.unwrap_or(7),
+                    },
+                    "turbulence" => NoisePattern::Turbulence {
+                        octaves: octaves.unwrap_or(7),
+                    },
+                    "worley" => NoisePattern::Worley,
+                    "marble" => NoisePattern::Marble {
+                        octaves: octaves.unwrap_or(7),
+                    },
+                    "wood" => NoisePattern::Wood {
+                        octaves: octaves.unwrap_or(4),
+                    },
+                    _ => {
+                        return Err(parse_error(
+                            token.line,
+                            token.column,
+                            format!(
+                                "unknown noise pattern '{name}', expected perlin, fbm, \
+                                 turbulence, worley, marble or wood"
+                            ),
+                        ))
+                    }
+                };
+                // the presets bring their own colors
+                let mut texture = match pattern {
+                    NoisePattern::Marble { .. } => NoiseTexture::marble(seed),
+                    NoisePattern::Wood { .. } => NoiseTexture::wood(seed),
+                    _ => NoiseTexture::new(pattern, seed),
+                }
+                .pattern(pattern)
+                .scale(scale);
+                if let Some(low) = low {
+                    texture = texture.low(low);
+                }
+                if let Some(high) = high {
+                    texture = texture.high(high);
+                }
+                Arc::new(texture)
+            }
+            _ => {
+                return Err(parse_error(
+                    kind_token.line,
+                    kind_token.column,
+                    format!("unknown texture type '{kind}'"),
+                ))
+            }
+        };
+        self.textures.insert(name, texture);
+        Ok(())
+    }
+
+    fn material(&mut self) -> Result<(), SceneError> {
+        let (name, name_token) = self.word("a material name")?;
+        if self.materials.contains_key(&name) {
+            return Err(parse_error(
+                name_token.line,
+                name_token.column,
+                format!("material '{name}' is already defined"),
+            ));
+        }
+
+        let (kind, kind_token) = self.word("a material type")?;
+        if !MATERIAL_TYPES.contains(&kind.as_str()) {
+            return Err(parse_error(
+                kind_token.line,
+                kind_token.column,
+                format!("unknown material type '{kind}'"),
+            ));
+        }
+        let mut albedo: Arc<dyn Texture> = Arc::new(Color::all(0.5));
+        let mut fuzz = 0.;
+        let mut ior = 1.5;
+        let mut emit: Arc<dyn Texture> = Arc::new(Color::white());
+        let mut roughness = 0.;
+        let mut conductor = Conductor::aluminium(0.);
+        let (mut eta, mut k) = (None, None);
+        let mut principled = Principled::new(Color::all(0.5));
+
+        self.open()?;
+        while let Some((key, token)) = self.key()? {
+            match (kind.as_str(), key.as_str()) {
+                ("lambertian" | "metal", "albedo") => albedo = self.texture()?,
+                ("metal", "fuzz") => fuzz = self.float()?,
+                ("dielectric" | "rough_dielectric", "ior") => ior = self.float()?,
+                ("conductor" | "rough_dielectric", "roughness") => roughness = self.float()?,
+                ("conductor", "preset") => {
+                    let (preset, token) = self.word("a conductor preset")?;
+                    conductor = Conductor::preset(&preset, 0.).ok_or_else(|| {
+                        parse_error(
+                            token.line,
+                            token.column,
+                            format!(
+                                "unknown conductor preset '{preset}', expected gold, copper or aluminium"
+                            ),
+                        )
+                    })?;
+                }
+                ("principled", "base_color") => principled = principled.base_color(self.texture()?),
+                ("principled", "metallic") => principled = principled.metallic(self.float()?),
+                ("principled", "roughness") => principled = principled.roughness(self.float()?),
+                ("principled", "specular") => principled = principled.specular(self.float()?),
+                ("principled", "clearcoat") => principled = principled.clearcoat(self.float()?),
+                ("principled", "sheen") => principled = principled.sheen(self.float()?),
+                ("principled", "transmission") => {
+                    principled = principled.transmission(self.float()?)
+                }
+                ("principled", "ior") => principled = principled.ior(self.float()?),
+                ("conductor", "eta") => eta = Some(self.color()?),
+                ("conductor", "k") => k = Some(self.color()?),
+                ("diffuse_light", "emit") => emit = self.texture()?,
+                _ => return Err(Self::unknown_key(&key, &token, &kind)),
+            }
+        }
+
+        let material: Arc<dyn Material> = match kind.as_str() {
+            "lambertian" => Lambertian::new_arc(albedo),
+            "metal" => Metal::new_arc(albedo, fuzz),
+            "dielectric" => Dielectric::new_arc(ior),
+            "rough_dielectric" => RoughDielectric::new_arc(ior, roughness),
+            "principled" => Arc::new(principled),
+            "conductor" => {
+                let eta = eta.unwrap_or_else(|| conductor.eta().clone());
+                let k = k.unwrap_or_else(|| conductor.k().clone());
+                Conductor::new_arc(eta, k, roughness)
+            }
+            "diffuse_light" => DiffuseLight::new_arc(emit),
+            _ => unreachable!(),
+        };
+        self.materials.insert(name, material);
+        Ok(())
+    }
+
+    fn sphere(&mut self, objects: &mut CollidableVec) -> Result<(), SceneError> {
+        let block = self.open()?;
+        let mut center = Point::origin();
+        let mut radius = None;
+        let mut material = None;
+
+        while let Some((key, token)) = self.key()? {
+            match key.as_str() {
+                "center" => center = self.point()?,
+                "radius" => radius = Some(self.float()?),
+                "material" => material = Some(self.material_ref()?),
+                _ => return Err(Self::unknown_key(&key, &token, "sphere")),
+            }
+        }
+
+        let radius = radius.ok_or_else(|| Self::missing(&block, "sphere", "radius"))?;
+        let material = material.ok_or_else(|| Self::missing(&block, "sphere", "material"))?;
+        objects.push(Sphere::boxed(center, radius, material));
+        Ok(())
+    }
+
+    fn triangle(&mut self, objects: &mut CollidableVec) -> Result<(), SceneError> {
+        let block = self.open()?;
+        let mut vertices = [None; 3];
+        let mut material = None;
+
+        while let Some((key, token)) = self.key()? {
+            match key.as_str() {
+                "a" => vertices[0] = Some(self.point()?),
+                "b" => vertices[1] = Some(self.point()?),
+                "c" => vertices[2] = Some(self.point()?),
+                "material" => material = Some(self.material_ref()?),
+                _ => return Err(Self::unknown_key(&key, &token, "triangle")),
+            }
+        }
+
+        let [a, b, c] = [("a", 0), ("b", 1), ("c", 2)]
+            .map(|(key, i)| vertices[i].ok_or_else(|| Self::missing(&block, "triangle", key)));
+        let material = material.ok_or_else(|| Self::missing(&block, "triangle", "material"))?;
+        objects.push(Triangle::boxed(a?, b?, c?, material));
+        Ok(())
+    }
+
+    fn mesh(&mut self, objects: &mut CollidableVec) -> Result<(), SceneError> {
+        let block = self.open()?;
+        let mut file = None;
+        let mut material = None;
+
+        while let Some((key, token)) = self.key()? {
+            match key.as_str() {
+                "file" => file = Some((self.string("a file path")?, token)),
+                "material" => material = Some(self.material_ref()?),
+                _ => return Err(Self::unknown_key(&key, &token, "mesh")),
+            }
+        }
+
+        let (file, token) = file.ok_or_else(|| Self::missing(&block, "mesh", "file"))?;
+        let meshes = load_obj(self.base_dir.join(file)).map_err(|source| SceneError::Obj {
+            line: token.line,
+            column: token.column,
+            source,
+        })?;
+
+        for mesh in meshes {
+            let mesh: TriangleMesh = match &material {
+                Some(material) => mesh.material(material.clone()),
+                None => mesh,
+            };
+            // one hierarchy per mesh keeps it a single object in the object ID pass
+            objects.push(mesh.into_bvh().into());
+        }
+        Ok(())
+    }
+}
+
+/// Parses a scene, resolving mesh paths against `base_dir`.
+pub fn parse_scene(source: &str, base_dir: &Path) -> Result<SceneDescription, SceneError> {
+    let tokens = tokenize(source)?;
+    let last_line = source.lines().count().max(1);
+    let last_column = source.lines().last().map_or(0, |l| l.chars().count()) + 1;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        base_dir: base_dir.to_owned(),
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        end: (last_line, last_column),
+    };
+
+    let mut objects = CollidableVec::new();
+    let mut camera = Camera::new();
+    let mut settings = SceneSettings::default();
+    let mut background = None;
+
+    while parser.peek().is_some() {
+        let (keyword, token) = parser.word("a top-level block")?;
+        match keyword.as_str() {
+            "camera" => camera = parser.camera(camera)?,
+            "render" => settings = parser.settings(settings)?,
+            "background" => background = Some(parser.background()?),
+            "texture" => parser.texture_block()?,
+            "material" => parser.material()?,
+            "sphere" => parser.sphere(&mut objects)?,
+            "triangle" => parser.triangle(&mut objects)?,
+            "mesh" => parser.mesh(&mut objects)?,
+            _ => {
+                return Err(parse_error(
+                    token.line,
+                    token.column,
+                    format!("unknown block '{keyword}'"),
+                ))
+            }
+        }
+    }
+
+    Ok(SceneDescription {
+        scene: match background {
+            Some(background) => Scene::new(objects, camera).background(background),
+            None => Scene::new(objects, camera),
+        },
+        settings,
+    })
+}
+
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<SceneDescription, SceneError> {
+    let path = path.as_ref();
+    let source =
+        std::fs::read_to_string(path).map_err(|err| SceneError::Io(path.to_owned(), err))?;
+    parse_scene(&source, path.parent().unwrap_or(Path::new("")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        parse_scene(source, Path::new(""))
+            .err()
+            .expect("scene should not parse")
+            .to_string()
+    }
+
+    #[test]
+    fn bundled_scenes_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "scene") {
+                let description =
+                    load_scene(&path).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+                assert!(description.settings.samples.is_some());
+                loaded += 1;
+            }
+        }
+        assert!(loaded >= 2);
+    }
+
+    #[test]
+    fn settings_are_read() {
+        let description = parse_scene(
+            "render { width 30 height 20 samples 5 max_depth 7 }",
+            Path::new(""),
+        )
+        .unwrap();
+        let settings = description.settings;
+        assert_eq!(settings.width, Some(30));
+        assert_eq!(settings.height, Some(20));
+        assert_eq!(settings.samples, Some(5));
+        assert_eq!(settings.max_depth, Some(7));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(
+            error("camera {\n    look_from 1 2 3\n    vfov x\n}"),
+            "3:10: expected a number, found 'x'"
+        );
+        assert_eq!(error("\n  cube { }"), "2:3: unknown block 'cube'");
+        assert_eq!(
+            error("material m lambertian { albedo 1 1 1 }\nsphere { radius 1 material n }"),
+            "2:28: unknown material 'n'"
+        );
+        assert_eq!(
+            error("material m plastic { albedo 1 1 1 }"),
+            "1:12: unknown material type 'plastic'"
+        );
+        assert_eq!(
+            error("background\n  sky { top 1 1 1 }"),
+            "2:3: unknown background type 'sky'"
+        );
+        assert_eq!(
+            error("sphere {\n  centre 0 0 0\n}"),
+            "2:3: unknown key 'centre' in sphere block"
+        );
+        assert_eq!(
+            error("material m metal { }\n  sphere { material m }"),
+            "2:10: sphere is missing required key 'radius'"
+        );
+        assert_eq!(
+            error("mesh { file \"teapot.obj }"),
+            "1:13: unterminated string"
+        );
+        assert_eq!(
+            error("render { samples 0 }"),
+            "1:18: expected a positive integer, found '0'"
+        );
+        assert_eq!(
+            error("render { max_depth -3 }"),
+            "1:20: expected a positive integer, found '-3'"
+        );
+        assert_eq!(
+            error("render { samples -3 }"),
+            "1:18: expected a positive integer, found '-3'"
+        );
+        assert_eq!(
+            error("render {\n  max_depth 3000000000\n}"),
+            "2:13: expected a positive integer, found '3000000000'"
+        );
+    }
+
+    #[test]
+    fn end_of_file_is_reported_where_the_file_ends() {
+        assert_eq!(
+            error("camera {\n    look_from 1 2"),
+            "2:18: expected a number, found end of file"
+        );
+        assert_eq!(
+            error("render { width 10"),
+            "1:18: expected a key or '}', found end of file"
+        );
+        assert_eq!(error("camera"), "1:7: expected '{', found end of file");
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{load_image, Color, Framebuffer, ImageError, Point, WrapMode};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A color that varies over a surface, looked up at each hit.
+pub trait Texture: Send + Sync {
+    fn value(&self, uv: (f64, f64), point: Point) -> Color;
+}
+
+/// A plain color is a texture that is the same everywhere.
+impl Texture for Color {
+    fn value(&self, _: (f64, f64), _: Point) -> Color {
+        self.clone()
+    }
+}
+
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, uv: (f64, f64), point: Point) -> Color {
+        (**self).value(uv, point)
+    }
+}
+
+enum CheckerSpace {
+    Uv { frequency: f64 },
+    World { size: f64 },
+}
+
+/// Alternates between two textures in a checkerboard.
+pub struct Checker {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    space: CheckerSpace,
+}
+
+impl Checker {
+    /// `frequency` squares along each of u and v.
+    pub fn uv(even: impl Texture + 'static, odd: impl Texture + 'static, frequency: f64) -> Self {
+        Self {
+            even: Arc::new(even),
+            odd: Arc::new(odd),
+            space: CheckerSpace::Uv { frequency },
+        }
+    }
+
+    /// Cubes of edge `size` filling space, so the pattern ignores how the surface is mapped.
+    pub fn world(even: impl Texture + 'static, odd: impl Texture + 'static, size: f64) -> Self {
+        Self {
+            even: Arc::new(even),
+            odd: Arc::new(odd),
+            space: CheckerSpace::World { size },
+        }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, uv: (f64, f64), point: Point) -> Color {
+        let cells = match self.space {
+            CheckerSpace::Uv { frequency } => {
+                (uv.0 * frequency).floor() as i64 + (uv.1 * frequency).floor() as i64
+            }
+            CheckerSpace::World { size } => {
+                (point.x / size).floor() as i64
+                    + (point.y / size).floor() as i64
+                    + (point.z / size).floor() as i64
+            }
+        };
+        if cells.rem_euclid(2) == 0 {
+            self.even.value(uv, point)
+        } else {
+            self.odd.value(uv, point)
+        }
+    }
+}
+
+/// An image stretched over the `[0, 1]` UV square, `v` growing upwards, repeating outside it
+/// unless told otherwise.
+pub struct ImageTexture {
+    image: Framebuffer,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(image: Framebuffer) -> Self {
+        assert!(
+            image.width() > 0 && image.height() > 0,
+            "an image texture cannot be empty"
+        );
+        Self {
+            image,
+            wrap: WrapMode::Repeat,
+        }
+    }
+
+    /// Loads a PPM, PFM, Radiance `.hdr` or PNG image.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+        Ok(Self::new(load_image(path)?))
+    }
+
+    #[must_use]
+    pub fn wrap(self, wrap: WrapMode) -> Self {
+        ImageTexture { wrap, ..self }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, (u, v): (f64, f64), _: Point) -> Color {
+        self.image.sample_bilinear(u, 1. - v, self.wrap, self.wrap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn red(texture: &dyn Texture, uv: (f64, f64), point: Point) -> f64 {
+        texture.value(uv, point).r
+    }
+
+    #[test]
+    fn checkers_alternate_across_cell_boundaries() {
+        let checker = Checker::uv(Color::all(1.), Color::all(0.), 4.);
+        let at = |u, v| red(&checker, (u, v), Point::origin());
+        assert_eq!(at(0.24, 0.1), 1.);
+        assert_eq!(at(0.26, 0.1), 0.);
+        assert_eq!(at(0.26, 0.26), 1.);
+        assert_eq!(at(0.1, 0.26), 0.);
+
+        let checker = Checker::world(Color::all(1.), Color::all(0.), 0.5);
+        let at = |x, y, z| red(&checker, (0., 0.), Point::new(x, y, z));
+        assert_eq!(at(0.4, 0.1, 0.1), 1.);
+        assert_eq!(at(0.6, 0.1, 0.1), 0.);
+        // cells keep alternating through zero rather than mirroring around it
+        assert_eq!(at(-0.1, 0.1, 0.1), 0.);
+        assert_eq!(at(-0.6, 0.1, 0.1), 1.);
+    }
+
+    #[test]
+    fn image_textures_put_the_first_row_at_the_top() {
+        // top row 0 and 1, bottom row 2 and 3
+        let pixels = (0..4).map(|i| Color::all(f64::from(i))).collect();
+        let texture = ImageTexture::new(Framebuffer::from_pixels(2, 2, pixels));
+        let at = |u, v| red(&texture, (u, v), Point::origin());
+        assert_eq!(at(0.25, 0.75), 0.);
+        assert_eq!(at(0.75, 0.75), 1.);
+        assert_eq!(at(0.25, 0.25), 2.);
+        assert_eq!(at(0.75, 0.25), 3.);
+        assert_eq!(at(1.25, -0.75), 2.);
+
+        let texture = texture.wrap(WrapMode::Clamp);
+        let at = |u, v| red(&texture, (u, v), Point::origin());
+        assert_eq!(at(1.5, 2.), 1.);
+        assert_eq!(at(0.5, 0.5), 1.5);
+    }
+}
@@ -0,0 +1,158 @@
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    #[default]
+    Scanline,
+    /// Outward from the center of the frame, so the subject usually resolves first.
+    Spiral,
+    /// Along a Hilbert curve, keeping consecutive tiles close together for cache locality.
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!(
+                "unknown tile order '{s}', expected scanline, spiral or hilbert"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+/// Covers a `width` x `height` frame with tiles of at most `tile_size` pixels square,
+/// listed in the order they should be rendered.
+pub fn tiles(width: usize, height: usize, tile_size: usize, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let cols = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+
+    let cells = match order {
+        TileOrder::Scanline => (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| (col, row)))
+            .collect(),
+        TileOrder::Spiral => spiral(cols, rows),
+        TileOrder::Hilbert => {
+            let side = cols.max(rows).next_power_of_two();
+            let mut cells: Vec<_> = (0..rows)
+                .flat_map(|row| (0..cols).map(move |col| (col, row)))
+                .collect();
+            cells.sort_by_key(|&(col, row)| hilbert_index(side, col, row));
+            cells
+        }
+    };
+
+    cells
+        .into_iter()
+        .map(|(col, row)| {
+            let x = col * tile_size;
+            let y = row * tile_size;
+            Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            }
+        })
+        .collect()
+}
+
+fn spiral(cols: usize, rows: usize) -> Vec<(usize, usize)> {
+    let total = cols * rows;
+    let mut cells = Vec::with_capacity(total);
+    let (mut col, mut row) = ((cols as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut step = 1;
+    let mut direction = 0;
+
+    let visit = |col: i64, row: i64, cells: &mut Vec<(usize, usize)>| {
+        if (0..cols as i64).contains(&col) && (0..rows as i64).contains(&row) {
+            cells.push((col as usize, row as usize));
+        }
+    };
+
+    visit(col, row, &mut cells);
+    while cells.len() < total {
+        // each run length is walked twice before growing by one
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction];
+            for _ in 0..step {
+                col += dx;
+                row += dy;
+                visit(col, row, &mut cells);
+            }
+            direction = (direction + 1) % 4;
+        }
+        step += 1;
+    }
+
+    cells
+}
+
+/// Position of `(x, y)` along the Hilbert curve filling a `side` x `side` grid.
+fn hilbert_index(side: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_order_covers_each_pixel_once() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            for (width, height, tile_size) in [(17, 5, 4), (5, 17, 4), (33, 33, 8), (1, 1, 16)] {
+                let mut covered = vec![0; width * height];
+                for tile in tiles(width, height, tile_size, order) {
+                    assert!(tile.width > 0 && tile.height > 0);
+                    assert!(tile.width <= tile_size && tile.height <= tile_size);
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            covered[y * width + x] += 1;
+                        }
+                    }
+                }
+                assert!(
+                    covered.iter().all(|&count| count == 1),
+                    "{order:?} tiles of {tile_size} don't cover {width}x{height} exactly once"
+                );
+            }
+            assert!(tiles(0, 0, 4, order).is_empty());
+            assert!(tiles(0, 7, 4, order).is_empty());
+        }
+    }
+}
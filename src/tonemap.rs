@@ -0,0 +1,221 @@
+use crate::{Color, Framebuffer};
+use std::str::FromStr;
+
+/// Curves squeezing scene radiance, which can be arbitrarily bright, into the displayable
+/// `[0, 1]` range.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ToneMapper {
+    /// Cuts everything above 1 off, so bright areas turn flat white.
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on luminance, which never quite reaches white.
+    Reinhard,
+    /// Reinhard with a chosen luminance that maps to pure white.
+    ExtendedReinhard { white: f64 },
+    /// Narkowicz's fit of the ACES filmic reference curve.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard),
+            "extended-reinhard" => Ok(ToneMapper::ExtendedReinhard { white: 4. }),
+            "aces" => Ok(ToneMapper::Aces),
+            "hable" => Ok(ToneMapper::Hable),
+            _ => Err(format!(
+                "unknown tone mapper '{s}', expected clamp, reinhard, extended-reinhard, \
+                 aces or hable"
+            )),
+        }
+    }
+}
+
+fn hable_partial(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.5, 0.1, 0.2, 0.02, 0.3);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+/// Scales the color so its luminance becomes `mapped`.
+fn with_luminance(color: &Color, mapped: impl Fn(f64) -> f64) -> Color {
+    let luminance = color.luminance();
+    if luminance <= 0. {
+        return Color::black();
+    }
+    color.clone() * (mapped(luminance) / luminance)
+}
+
+impl ToneMapper {
+    pub fn map(self, color: &Color) -> Color {
+        let per_channel = |f: fn(f64) -> f64| Color::new(f(color.r), f(color.g), f(color.b));
+        let mapped = match self {
+            ToneMapper::Clamp => color.clone(),
+            ToneMapper::Reinhard => with_luminance(color, |l| l / (1. + l)),
+            ToneMapper::ExtendedReinhard { white } => {
+                with_luminance(color, |l| l * (1. + l / (white * white)) / (1. + l))
+            }
+            ToneMapper::Aces => per_channel(|x| {
+                let x = 0.6 * x.max(0.);
+                (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            }),
+            ToneMapper::Hable => per_channel(|x| {
+                const WHITE: f64 = 11.2;
+                const EXPOSURE_BIAS: f64 = 2.;
+                hable_partial(EXPOSURE_BIAS * x.max(0.)) / hable_partial(WHITE)
+            }),
+        };
+        Color::new(
+            mapped.r.clamp(0., 1.),
+            mapped.g.clamp(0., 1.),
+            mapped.b.clamp(0., 1.),
+        )
+    }
+}
+
+/// Turns a linear render into display-ready linear values in `[0, 1]`: exposure, then white
+/// balance, then a tone curve. The sRGB transfer is left to the writers of integer formats.
+#[derive(Debug, Clone)]
+pub struct ToneMapping {
+    exposure: f64,
+    white_balance: Color,
+    operator: ToneMapper,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            exposure: 0.,
+            white_balance: Color::white(),
+            operator: ToneMapper::default(),
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Brightness change in stops: +1 doubles the light.
+    #[must_use]
+    pub fn exposure(self, exposure: f64) -> Self {
+        ToneMapping { exposure, ..self }
+    }
+
+    /// The color that should come out neutral, such as a gray card's under the scene's light.
+    /// Overall brightness is kept.
+    #[must_use]
+    pub fn white_balance(self, white: Color) -> Self {
+        assert!(
+            white.r > 0. && white.g > 0. && white.b > 0.,
+            "a white balance color needs every channel above zero"
+        );
+        ToneMapping {
+            white_balance: white,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn operator(self, operator: ToneMapper) -> Self {
+        ToneMapping { operator, ..self }
+    }
+
+    pub fn map(&self, color: &Color) -> Color {
+        let white = &self.white_balance;
+        let balance = Color::new(1. / white.r, 1. / white.g, 1. / white.b) * white.luminance();
+        let exposed = color.clone() * balance * 2f64.powf(self.exposure);
+        self.operator.map(&exposed)
+    }
+
+    pub fn apply(&self, image: &Framebuffer) -> Framebuffer {
+        let pixels = image.pixels().iter().map(|pixel| self.map(pixel)).collect();
+        Framebuffer::from_pixels(image.width(), image.height(), pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{linear_to_srgb, srgb_to_linear};
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-12, "{a} != {b}");
+    }
+
+    #[test]
+    fn curves_rise_from_black() {
+        let mappers = [
+            ToneMapper::Clamp,
+            ToneMapper::Reinhard,
+            ToneMapper::ExtendedReinhard { white: 4. },
+            ToneMapper::Aces,
+            ToneMapper::Hable,
+        ];
+        for mapper in mappers {
+            assert_eq!(mapper.map(&Color::black()).luminance(), 0., "{mapper:?}");
+            let mut previous = 0.;
+            for step in 0..=400 {
+                // logarithmically from 1e-4 to 1e4
+                let x = 10f64.powf(f64::from(step) / 50. - 4.);
+                let mapped = mapper.map(&Color::new(x, 0.5 * x, 0.25 * x)).r;
+                assert!(mapped >= previous, "{mapper:?} falls at {x}");
+                assert!(mapped <= 1.);
+                previous = mapped;
+            }
+            assert!(previous > 0.8, "{mapper:?} only reaches {previous}");
+        }
+
+        let white = ToneMapper::ExtendedReinhard { white: 4. }.map(&Color::all(4.));
+        assert_close(white.g, 1.);
+        assert!(ToneMapper::Reinhard.map(&Color::all(4.)).g < 1.);
+    }
+
+    #[test]
+    fn each_stop_doubles_the_light() {
+        let color = Color::new(0.05, 0.1, 0.2);
+        let brighter = ToneMapping::new().exposure(1.).map(&color);
+        let darker = ToneMapping::new().exposure(-2.).map(&color);
+        assert_close(brighter.r, 0.1);
+        assert_close(brighter.b, 0.4);
+        assert_close(darker.g, 0.025);
+    }
+
+    #[test]
+    fn neutral_white_balance_changes_nothing() {
+        let color = Color::new(0.3, 0.6, 0.1);
+        for gray in [Color::white(), Color::all(0.18), Color::all(3.)] {
+            let balanced = ToneMapping::new().white_balance(gray).map(&color);
+            assert_close(balanced.r, color.r);
+            assert_close(balanced.g, color.g);
+            assert_close(balanced.b, color.b);
+        }
+
+        // a warm light's white comes out gray
+        let warm = Color::new(1., 0.8, 0.5);
+        let balanced = ToneMapping::new().white_balance(warm.clone()).map(&warm);
+        assert_close(balanced.r, balanced.g);
+        assert_close(balanced.g, balanced.b);
+    }
+
+    #[test]
+    fn srgb_transfer_follows_the_piecewise_spec() {
+        // the linear toe and the power segment meet at 0.0031308, encoded as 0.04045
+        let knee = 0.003_130_8;
+        assert_close(linear_to_srgb(knee), 12.92 * knee);
+        assert!((linear_to_srgb(knee + 1e-12) - 0.040_45).abs() < 1e-6);
+        assert!((1.055 * knee.powf(1. / 2.4) - 0.055 - 12.92 * knee).abs() < 1e-6);
+        assert_close(linear_to_srgb(0.5), 1.055 * 0.5f64.powf(1. / 2.4) - 0.055);
+        assert_close(linear_to_srgb(0.), 0.);
+        assert_close(linear_to_srgb(1.), 1.);
+        for step in 0..=100 {
+            let value = f64::from(step) / 100.;
+            assert_close(srgb_to_linear(linear_to_srgb(value)), value);
+        }
+    }
+}
@@ -0,0 +1,442 @@
+use crate::{
+    rand, Aabb, Bvh, Collidable, CollidableVec, Collision, Emitter, Facing, LightSample, Material,
+    Point, Ray, Vec3,
+};
+use std::sync::Arc;
+
+const BBOX_PADDING: f64 = 1e-8;
+
+struct TriangleHit {
+    dist: f64,
+    barycentric: [f64; 3],
+}
+
+/// Watertight ray/triangle test (Woop, Benthin & Wald 2013): shared edges never leak rays.
+fn intersect(ray: &Ray, vertices: [Point; 3], t_min: f64, t_max: f64) -> Option<TriangleHit> {
+    let d = ray.direction;
+    let abs_d = Vec3::new(d.x.abs(), d.y.abs(), d.z.abs());
+    let kz = Aabb::new(Point::origin(), abs_d).longest_axis();
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if d[kz] < 0. {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    let sx = d[kx] / d[kz];
+    let sy = d[ky] / d[kz];
+    let sz = 1. / d[kz];
+
+    let [a, b, c] = vertices.map(|p| p - ray.origin);
+    let shear = |p: Point| (p[kx] - sx * p[kz], p[ky] - sy * p[kz], sz * p[kz]);
+    let (ax, ay, az) = shear(a);
+    let (bx, by, bz) = shear(b);
+    let (cx, cy, cz) = shear(c);
+
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    if (u < 0. || v < 0. || w < 0.) && (u > 0. || v > 0. || w > 0.) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0. {
+        return None;
+    }
+
+    let dist = (u * az + v * bz + w * cz) / det;
+    if dist < t_min || t_max < dist {
+        return None;
+    }
+
+    Some(TriangleHit {
+        dist,
+        barycentric: [u / det, v / det, w / det],
+    })
+}
+
+fn interpolate(values: [Point; 3], barycentric: [f64; 3]) -> Point {
+    barycentric[0] * values[0] + barycentric[1] * values[1] + barycentric[2] * values[2]
+}
+
+fn triangle_bbox(vertices: [Point; 3]) -> Aabb {
+    Aabb::from_points(vertices[0], vertices[1])
+        .including(vertices[2])
+        .padded(BBOX_PADDING)
+}
+
+/// Solid-angle density of reaching `point` on a triangle from `origin`, given its area.
+fn area_to_solid_angle(vertices: [Point; 3], origin: Point, point: Point) -> f64 {
+    let [a, b, c] = vertices;
+    let cross = (b - a).cross(c - a);
+    let area = 0.5 * cross.len();
+    let to_point = point - origin;
+    let cosine = cross.unit().dot_product(to_point.unit()).abs();
+    if area <= 0. || cosine <= 1e-8 {
+        return 0.;
+    }
+    to_point.len_sq() / (cosine * area)
+}
+
+fn sample_triangle(vertices: [Point; 3], origin: Point) -> Option<LightSample> {
+    // uniform over the area; the square root keeps points from bunching at a corner
+    let s = rand().sqrt();
+    let t = rand();
+    let point = interpolate(vertices, [1. - s, s * (1. - t), s * t]);
+    let pdf = area_to_solid_angle(vertices, origin, point);
+    (pdf > 0.).then(|| LightSample {
+        direction: point - origin,
+        pdf,
+    })
+}
+
+fn triangle_pdf(vertices: [Point; 3], origin: Point, direction: Vec3) -> f64 {
+    let ray = Ray::new(origin, direction);
+    match intersect(&ray, vertices, 0.001, f64::INFINITY) {
+        Some(hit) => area_to_solid_angle(vertices, origin, ray.at(hit.dist)),
+        None => 0.,
+    }
+}
+
+pub struct Triangle {
+    vertices: [Point; 3],
+    material: Arc<dyn Material>,
+}
+
+unsafe impl Send for Triangle {}
+unsafe impl Sync for Triangle {}
+
+impl Triangle {
+    #[must_use]
+    pub fn new(a: Point, b: Point, c: Point, material: Arc<dyn Material>) -> Self {
+        Self {
+            vertices: [a, b, c],
+            material,
+        }
+    }
+
+    #[must_use]
+    pub fn boxed(a: Point, b: Point, c: Point, material: Arc<dyn Material>) -> Box<Self> {
+        Box::new(Self::new(a, b, c, material))
+    }
+}
+
+impl Collidable for Triangle {
+    fn collide(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Collision> {
+        let hit = intersect(ray, self.vertices, t_min, t_max)?;
+        let [a, b, c] = self.vertices;
+        let outward_normal = (b - a).cross(c - a).unit();
+        let [_, b1, b2] = hit.barycentric;
+
+        let mut collision = Collision::new(
+            interpolate(self.vertices, hit.barycentric),
+            outward_normal,
+            hit.dist,
+            Facing::Front,
+            self.material.clone(),
+        )
+        .with_uv(b1 + b2, b2);
+        collision.set_face_normal(ray, outward_normal);
+        Some(collision)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle_bbox(self.vertices))
+    }
+
+    fn emitters(&self) -> Vec<Box<dyn Emitter>> {
+        if !self.material.is_emissive() {
+            return Vec::new();
+        }
+        let [a, b, c] = self.vertices;
+        vec![Box::new(Triangle::new(a, b, c, self.material.clone()))]
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.material.clone()]
+    }
+}
+
+impl Emitter for Triangle {
+    fn sample(&self, origin: Point) -> Option<LightSample> {
+        sample_triangle(self.vertices, origin)
+    }
+
+    fn pdf(&self, origin: Point, direction: Vec3) -> f64 {
+        triangle_pdf(self.vertices, origin, direction)
+    }
+}
+
+/// Indexed triangles sharing one vertex buffer, with optional per-vertex normals and UVs.
+pub struct TriangleMesh {
+    positions: Vec<Point>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    indices: Vec<[usize; 3]>,
+    material: Arc<dyn Material>,
+}
+
+unsafe impl Send for TriangleMesh {}
+unsafe impl Sync for TriangleMesh {}
+
+impl TriangleMesh {
+    #[must_use]
+    pub fn new(
+        positions: Vec<Point>,
+        indices: Vec<[usize; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(
+            indices.iter().flatten().all(|&i| i < positions.len()),
+            "triangle index out of range of the vertex buffer"
+        );
+        Self {
+            positions,
+            normals: None,
+            uvs: None,
+            indices,
+            material,
+        }
+    }
+
+    #[must_use]
+    pub fn normals(self, normals: Vec<Vec3>) -> Self {
+        assert_eq!(
+            normals.len(),
+            self.positions.len(),
+            "a mesh needs exactly one normal per vertex"
+        );
+        TriangleMesh {
+            normals: Some(normals),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn uvs(self, uvs: Vec<(f64, f64)>) -> Self {
+        assert_eq!(
+            uvs.len(),
+            self.positions.len(),
+            "a mesh needs exactly one uv per vertex"
+        );
+        TriangleMesh {
+            uvs: Some(uvs),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn material(self, material: Arc<dyn Material>) -> Self {
+        TriangleMesh { material, ..self }
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Splits the mesh into one collidable per face, all referencing the shared buffers.
+    #[must_use]
+    pub fn into_triangles(self) -> CollidableVec {
+        let mesh = Arc::new(self);
+        (0..mesh.len())
+            .map(|face| {
+                Box::new(MeshTriangle {
+                    mesh: mesh.clone(),
+                    face,
+                }) as _
+            })
+            .collect()
+    }
+
+    #[must_use]
+    pub fn into_bvh(self) -> Bvh {
+        Bvh::new(self.into_triangles())
+    }
+
+    fn face_vertices(&self, face: usize) -> [Point; 3] {
+        self.indices[face].map(|i| self.positions[i])
+    }
+}
+
+pub struct MeshTriangle {
+    mesh: Arc<TriangleMesh>,
+    face: usize,
+}
+
+impl Collidable for MeshTriangle {
+    fn collide(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Collision> {
+        let vertices = self.mesh.face_vertices(self.face);
+        let hit = intersect(ray, vertices, t_min, t_max)?;
+        let indices = self.mesh.indices[self.face];
+        let [a, b, c] = vertices;
+        let outward_normal = (b - a).cross(c - a).unit();
+
+        let (u, v) = match &self.mesh.uvs {
+            Some(uvs) => {
+                let uv = indices.map(|i| Point::new(uvs[i].0, uvs[i].1, 0.));
+                let p = interpolate(uv, hit.barycentric);
+                (p.x, p.y)
+            }
+            None => {
+                let [_, b1, b2] = hit.barycentric;
+                (b1 + b2, b2)
+            }
+        };
+
+        let mut collision = Collision::new(
+            interpolate(vertices, hit.barycentric),
+            outward_normal,
+            hit.dist,
+            Facing::Front,
+            self.mesh.material.clone(),
+        )
+        .with_uv(u, v);
+        collision.set_face_normal(ray, outward_normal);
+
+        // facing comes from the true surface, shading from the interpolated normal
+        if let Some(normals) = &self.mesh.normals {
+            let shading = interpolate(indices.map(|i| normals[i]), hit.barycentric);
+            if !shading.is_near_zero() {
+                collision.normal = match collision.facing {
+                    Facing::Front => shading.unit(),
+                    Facing::Back => -shading.unit(),
+                };
+            }
+        }
+        Some(collision)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle_bbox(self.mesh.face_vertices(self.face)))
+    }
+
+    fn emitters(&self) -> Vec<Box<dyn Emitter>> {
+        if !self.mesh.material.is_emissive() {
+            return Vec::new();
+        }
+        vec![Box::new(MeshTriangle {
+            mesh: self.mesh.clone(),
+            face: self.face,
+        })]
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.mesh.material.clone()]
+    }
+}
+
+impl Emitter for MeshTriangle {
+    fn sample(&self, origin: Point) -> Option<LightSample> {
+        sample_triangle(self.mesh.face_vertices(self.face), origin)
+    }
+
+    fn pdf(&self, origin: Point, direction: Vec3) -> f64 {
+        triangle_pdf(self.mesh.face_vertices(self.face), origin, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rand_range, Color, Lambertian};
+
+    /// The unit square in the z = 0 plane, split along its diagonal into two triangles
+    /// facing +z.
+    fn square() -> TriangleMesh {
+        let positions = vec![
+            Point::new(0., 0., 0.),
+            Point::new(1., 0., 0.),
+            Point::new(1., 1., 0.),
+            Point::new(0., 1., 0.),
+        ];
+        TriangleMesh::new(
+            positions,
+            vec![[0, 1, 2], [0, 2, 3]],
+            Lambertian::new_arc(Color::all(0.5)),
+        )
+    }
+
+    #[test]
+    fn shared_edges_and_vertices_have_no_gaps() {
+        let triangles = square().into_triangles();
+        let mut targets: Vec<Point> = (0..=16)
+            .map(|i| Point::new(i as f64 / 16., i as f64 / 16., 0.))
+            .collect();
+        targets.extend([
+            Point::new(1., 0., 0.),
+            Point::new(0., 1., 0.),
+            Point::new(0.5, 0.5, 0.),
+        ]);
+
+        for target in targets {
+            for _ in 0..200 {
+                // any direction from above, aimed right at the edge or vertex
+                let direction = Vec3::new(rand_range(-1., 1.), rand_range(-1., 1.), -1.);
+                let ray = Ray::new(target - 3. * direction, direction);
+                let hits = triangles
+                    .iter()
+                    .filter(|triangle| triangle.collide(&ray, 0.001, f64::INFINITY).is_some())
+                    .count();
+                assert!(hits >= 1, "ray at {target:?} slipped between the triangles");
+                let collision = triangles.collide(&ray, 0.001, f64::INFINITY).unwrap();
+                assert!((collision.point - target).len() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn back_faces_are_hit_with_the_normal_flipped() {
+        let triangle = Triangle::new(
+            Point::new(0., 0., 0.),
+            Point::new(1., 0., 0.),
+            Point::new(0., 1., 0.),
+            Lambertian::new_arc(Color::all(0.5)),
+        );
+        let from_above = Ray::new(Point::new(0.25, 0.25, 1.), Vec3::from_z(-1.));
+        let front = triangle.collide(&from_above, 0.001, f64::INFINITY).unwrap();
+        assert!(matches!(front.facing, Facing::Front));
+        assert_eq!(front.normal.z, 1.);
+        assert_eq!(front.dist, 1.);
+
+        let from_below = Ray::new(Point::new(0.25, 0.25, -2.), Vec3::from_z(1.));
+        let back = triangle.collide(&from_below, 0.001, f64::INFINITY).unwrap();
+        assert!(matches!(back.facing, Facing::Back));
+        assert_eq!(back.normal.z, -1.);
+        assert_eq!(back.dist, 2.);
+
+        let beside = Ray::new(Point::new(0.75, 0.75, 1.), Vec3::from_z(-1.));
+        assert!(triangle.collide(&beside, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn mesh_bounds_cover_every_vertex() {
+        let positions = vec![
+            Point::new(-2., 0., 1.),
+            Point::new(3., -1., 0.),
+            Point::new(0., 4., -5.),
+            Point::new(1., 1., 1.),
+        ];
+        let mesh = TriangleMesh::new(
+            positions,
+            vec![[0, 1, 2], [1, 2, 3]],
+            Lambertian::new_arc(Color::all(0.5)),
+        );
+        assert_eq!(mesh.len(), 2);
+
+        let bounds = mesh.into_bvh().bounding_box().unwrap();
+        for (actual, expected) in [
+            (bounds.min, Point::new(-2., -1., -5.)),
+            (bounds.max, Point::new(3., 4., 1.)),
+        ] {
+            assert!(
+                (actual - expected).len() < 1e-6,
+                "{actual:?} != {expected:?}"
+            );
+        }
+    }
+}
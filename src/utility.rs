@@ -1,4 +1,9 @@
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
 
 pub fn deg_to_rad(deg: f64) -> f64 {
     deg * std::f64::consts::PI / 180.
@@ -14,8 +19,13 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     }
 }
 
+/// Reseeds the calling thread's generator so everything it draws afterwards is reproducible.
+pub fn seed_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
 pub fn rand_range(min: f64, max: f64) -> f64 {
-    thread_rng().gen_range(min..max)
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
 }
 pub fn rand() -> f64 {
     rand_range(0., 1.)
@@ -0,0 +1,540 @@
+//! Just enough of zlib (RFC 1950) and DEFLATE (RFC 1951) for the image formats.
+
+use crate::ImageError;
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order code length code lengths are stored in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn corrupt<T>(message: &str) -> Result<T, ImageError> {
+    Err(ImageError::Format(format!(
+        "corrupt deflate stream: {message}"
+    )))
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// Reads `n <= 16` bits, least significant first.
+    fn bits(&mut self, n: u32) -> Result<u32, ImageError> {
+        while self.count < n {
+            let Some(&byte) = self.data.get(self.pos) else {
+                return corrupt("unexpected end of data");
+            };
+            self.buffer |= u32::from(byte) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code, decoded one bit at a time as in zlib's `puff`.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, ImageError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return corrupt("over-subscribed Huffman code");
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[usize::from(offsets[usize::from(length)])] = symbol as u16;
+                offsets[usize::from(length)] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, ImageError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(count);
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        corrupt("invalid Huffman code")
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), ImageError> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), ImageError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return corrupt("too many codes");
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let Some(&previous) = i.checked_sub(1).and_then(|p| lengths.get(p)) else {
+                    return corrupt("repeat with no previous length");
+                };
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return corrupt("code lengths overrun");
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return corrupt("no end-of-block code");
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), ImageError> {
+    loop {
+        let symbol = usize::from(literals.decode(reader)?);
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return corrupt("invalid length code");
+                }
+                let length = usize::from(LENGTH_BASE[index])
+                    + reader.bits(u32::from(LENGTH_EXTRA[index]))? as usize;
+
+                let index = usize::from(distances.decode(reader)?);
+                if index >= DIST_BASE.len() {
+                    return corrupt("invalid distance code");
+                }
+                let distance = usize::from(DIST_BASE[index])
+                    + reader.bits(u32::from(DIST_EXTRA[index]))? as usize;
+                if distance > out.len() {
+                    return corrupt("distance reaches before the start of the data");
+                }
+
+                // the copy may overlap what it is producing, so go byte by byte
+                let start = out.len() - distance;
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+/// Decompresses a raw DEFLATE stream.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let mut reader = BitReader::new(data);
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let pos = reader.pos;
+                let Some(header) = data.get(pos..pos + 4) else {
+                    return corrupt("truncated stored block");
+                };
+                let length = usize::from(u16::from_le_bytes([header[0], header[1]]));
+                let complement = u16::from_le_bytes([header[2], header[3]]);
+                if length as u16 != !complement {
+                    return corrupt("stored block length check failed");
+                }
+                let Some(bytes) = data.get(pos + 4..pos + 4 + length) else {
+                    return corrupt("truncated stored block");
+                };
+                out.extend_from_slice(bytes);
+                reader.pos = pos + 4 + length;
+            }
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            _ => return corrupt("invalid block type"),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Decompresses a zlib stream, checking its header and checksum.
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    if data.len() < 6 {
+        return corrupt("zlib stream too short");
+    }
+    let (cmf, flags) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || (u16::from(cmf) << 8 | u16::from(flags)) % 31 != 0 {
+        return corrupt("bad zlib header");
+    }
+    if flags & 0x20 != 0 {
+        return corrupt("preset dictionaries are not supported");
+    }
+
+    let out = inflate(&data[2..data.len() - 4])?;
+    let expected = u32::from_be_bytes(data[data.len() - 4..].try_into().unwrap());
+    if adler32(&out) != expected {
+        return corrupt("checksum mismatch");
+    }
+    Ok(out)
+}
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// How many earlier positions with the same hash are tried before settling for the best so far.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            out: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// Writes the low `n` bits of `value`, least significant first.
+    fn bits(&mut self, value: u32, n: u32) {
+        self.buffer |= u64::from(value) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed starting from their most significant bit.
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+/// Writes a symbol of the fixed literal/length code.
+fn fixed_literal(writer: &mut BitWriter, symbol: usize) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE
+        .iter()
+        .rposition(|&base| usize::from(base) <= length)
+        .unwrap();
+    fixed_literal(writer, 257 + index);
+    writer.bits(
+        (length - usize::from(LENGTH_BASE[index])) as u32,
+        u32::from(LENGTH_EXTRA[index]),
+    );
+
+    let index = DIST_BASE
+        .iter()
+        .rposition(|&base| usize::from(base) <= distance)
+        .unwrap();
+    writer.code(index as u32, 5);
+    writer.bits(
+        (distance - usize::from(DIST_BASE[index])) as u32,
+        u32::from(DIST_EXTRA[index]),
+    );
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let key = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+    (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compresses `data` into a raw DEFLATE stream: greedy LZ77 matching over hash chains, coded
+/// with the fixed Huffman tables, or stored as is if that does not help. Far from the best
+/// ratio, but simple and quick.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    // the most recent position with each hash, and for each position the one before it
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW_SIZE];
+    let insert = |pos: usize, head: &mut [usize], previous: &mut [usize]| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(&data[pos..]);
+            previous[pos % WINDOW_SIZE] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(&data[pos..])];
+            let mut chain = 0;
+            while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    (best_length, best_distance) = (length, pos - candidate);
+                    if length == max_length {
+                        break;
+                    }
+                }
+                let next = previous[candidate % WINDOW_SIZE];
+                // entries older than the window have been overwritten by newer positions
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            write_match(&mut writer, best_length, best_distance);
+            for p in pos..pos + best_length {
+                insert(p, &mut head, &mut previous);
+            }
+            pos += best_length;
+        } else {
+            fixed_literal(&mut writer, usize::from(data[pos]));
+            insert(pos, &mut head, &mut previous);
+            pos += 1;
+        }
+    }
+
+    fixed_literal(&mut writer, 256);
+    let compressed = writer.finish();
+    if compressed.len() > data.len() + 5 * data.len().div_ceil(0xffff).max(1) {
+        return store(data);
+    }
+    compressed
+}
+
+/// Stored blocks, for data that does not get any smaller.
+fn store(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 5 * data.len().div_ceil(0xffff) + 5);
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let length = block.len() as u16;
+        out.push(u8::from(blocks.peek().is_none()));
+        out.extend(length.to_le_bytes());
+        out.extend((!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out
+}
+
+/// Wraps [`deflate`] output in a zlib header and checksum.
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window, and a check value making the header a multiple of 31
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate(data));
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let compressed = zlib_compress(data);
+        assert_eq!(zlib_decompress(&compressed).unwrap(), data);
+        compressed
+    }
+
+    #[test]
+    fn empty_input_round_trips() {
+        round_trip(&[]);
+        assert_eq!(inflate(&store(&[])).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn random_data_falls_back_to_stored_blocks() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let data: Vec<u8> = (0..200_000).map(|_| rng.gen()).collect();
+        let compressed = round_trip(&data);
+        // four stored blocks, each with five bytes of framing, plus the zlib wrapper
+        assert_eq!(compressed.len(), data.len() + 4 * 5 + 6);
+
+        for len in [1, 0xffff, 0x10000, 3 * 0xffff] {
+            assert_eq!(inflate(&store(&data[..len])).unwrap(), &data[..len]);
+        }
+    }
+
+    #[test]
+    fn repetitive_data_shrinks() {
+        let mut data = vec![0u8; 100_000];
+        data.extend(b"abcdefg".iter().cycle().take(100_000));
+        data.extend((0..100_000u32).map(|i| (i / 300) as u8));
+        let compressed = round_trip(&data);
+        assert!(
+            compressed.len() < data.len() / 50,
+            "{} bytes",
+            compressed.len()
+        );
+    }
+
+    #[test]
+    fn mixed_data_round_trips() {
+        // random stretches interleaved with repeats of earlier ones at all distances, some
+        // beyond the window
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut data: Vec<u8> = Vec::new();
+        while data.len() < 300_000 {
+            if data.len() > 1000 && rng.gen_bool(0.5) {
+                let start = rng.gen_range(0..data.len() - 500);
+                let len = rng.gen_range(1..500);
+                data.extend_from_within(start..start + len);
+            } else {
+                let len = rng.gen_range(1..100);
+                data.extend((0..len).map(|_| rng.gen_range(0..4u8)));
+            }
+        }
+        round_trip(&data);
+    }
+
+    #[test]
+    fn corrupt_streams_are_rejected() {
+        let mut compressed = zlib_compress(b"hello, hello, hello");
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+        assert!(zlib_decompress(&compressed).is_err());
+        assert!(zlib_decompress(&[0x78, 0x01]).is_err());
+    }
+}